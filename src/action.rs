@@ -5,20 +5,206 @@
 use std::{
     fmt::Display, 
     io, 
+    marker::PhantomData,
     str::FromStr,
 };
 
-use crate::cubelet::Axis;
+use crate::cubelet::{Axis, Rotation};
 
 pub trait Action: Clone + Copy + PartialEq + Eq + Sized + Into<Move> + Display + 'static {
+    /// Every action in the generating set, ordered by [`Action::index`]
     const ALL: &'static [Self];
+    /// A name for the set of actions that will not change between releases, used to label stored
+    /// data
+    const TAG: &'static str;
     fn inverse(&self) -> Self;
     fn from_move(m: Move) -> Vec<Self>;
+
+    /// A stable integer for the action, suitable for the output layer of a network. These will
+    /// not change between releases and `Self::ALL[a.index()] == a` always holds.
+    fn index(&self) -> usize;
+
+    /// The inverse of [`Action::index`]
+    fn from_index(index: usize) -> Option<Self> {
+        Self::ALL.get(index).copied()
+    }
 }
 
 // #[derive(Debug)]
 // pub(crate) enum ActionType { Move, Turn, QuarterTurn }
 
+/// A way of counting the length of a sequence of moves. Every [`Action`] counts moves in its own
+/// metric: [`Move`] is the axial metric, [`Turn`] is the half turn metric (HTM) and [`QuarterTurn`]
+/// is the quarter turn metric (QTM). [`SliceTurn`] is the slice turn metric (STM).
+pub trait Metric {
+    /// The number of actions `m` counts as
+    fn length(m: Move) -> usize;
+}
+
+impl<A: Action> Metric for A {
+    fn length(m: Move) -> usize {
+        if m.1 == 0 && m.2 == 0 { 0 } else { A::from_move(m).len() }
+    }
+}
+
+/// The slice turn metric, where turning a middle slice counts as one move. There are no centers in
+/// a [`crate::Cube`], so a slice turn leaves it in the same state as turning the two outer faces of
+/// its axis the same way, e.g. `M` and `L' R`. This counts by state: a turn of both faces on one
+/// axis counts as the slice turn it could be replaced with, so `L R'` is 1 here where published
+/// STM counts it as the 2 face turns it is written with.
+#[derive(Clone, Copy, Debug)]
+pub struct SliceTurn;
+
+impl Metric for SliceTurn {
+    fn length(m: Move) -> usize {
+        let Move(_, rot1, rot2) = m;
+        let turned = |rot: u8| (rot & 3 != 0) as usize;
+        let faces = turned(rot1) + turned(rot2);
+        // Turn the slice to line up with one face, then turn the other face
+        let slice = 1 + turned(rot1 + 4 - rot2);
+        faces.min(slice)
+    }
+}
+
+/// The history needed to enumerate canonical sequences of actions: the combined [`Move`] of the
+/// current run of actions on one axis, if there is one.
+///
+/// Actions on the same axis commute, so every run of actions on one axis has exactly one canonical
+/// spelling, the one given by [`Action::from_move`] for the run's combined move. A sequence is
+/// canonical when each of its runs is spelled that way. This rules out `R L` (only `L R` is
+/// allowed), same-face repeats like `R R2` and cancellations like `R R'`, while still allowing
+/// `L L` for quarter turns because that is how [`QuarterTurn`] spells `L2`. Every prefix of a
+/// canonical sequence is canonical, so searches can prune as they go.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Canonical(Option<Move>);
+
+impl Canonical {
+    /// The state before any actions have been made
+    pub const START: Self = Self(None);
+
+    /// The state after `action`, or `None` if `action` would make the sequence non-canonical
+    pub fn next<A: Action>(self, action: A) -> Option<Self> {
+        let m = action.into();
+        match self.0 {
+            Some(run) if run.0 == m.0 => {
+                let combined = Move(run.0, (run.1 + m.1) % 4, (run.2 + m.2) % 4);
+                if combined.1 == 0 && combined.2 == 0 {
+                    return None;
+                }
+                let before = A::from_move(run);
+                let after = A::from_move(combined);
+                if after.len() == before.len() + 1 && after.starts_with(&before) && after.last() == Some(&action) {
+                    Some(Self(Some(combined)))
+                } else {
+                    None
+                }
+            }
+            _ => Some(Self(Some(m))),
+        }
+    }
+
+    /// All the actions that may follow in a canonical sequence, with their resulting states
+    pub fn successors<A: Action>(self) -> Successors<A> {
+        Successors { state: self, actions: A::ALL.iter() }
+    }
+
+    /// Whether every prefix of `actions` is canonical
+    pub fn is_canonical<A: Action>(actions: &[A]) -> bool {
+        actions.iter()
+            .try_fold(Self::START, |state, &a| state.next(a))
+            .is_some()
+    }
+}
+
+/// Iterator over the canonical successors of a [`Canonical`] state
+#[derive(Clone, Debug)]
+pub struct Successors<A: 'static> {
+    state: Canonical,
+    actions: std::slice::Iter<'static, A>,
+}
+
+impl<A: Action> Iterator for Successors<A> {
+    type Item = (A, Canonical);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let state = self.state;
+        self.actions.find_map(|&a| state.next(a).map(|next| (a, next)))
+    }
+}
+
+/// A set of actions stored as a bitmask over [`Action::index`]. No action type has more than 64
+/// actions, so the mask always fits in a `u64`.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub struct ActionSet<A> {
+    bits: u64,
+    _phantom: PhantomData<A>,
+}
+
+impl<A: Action> ActionSet<A> {
+    pub const fn new() -> Self {
+        Self { bits: 0, _phantom: PhantomData }
+    }
+
+    /// The set with the given mask. Bits past `A::ALL.len()` are dropped.
+    pub fn from_bits(bits: u64) -> Self {
+        let mask = if A::ALL.len() == 64 { u64::MAX } else { (1 << A::ALL.len()) - 1 };
+        Self { bits: bits & mask, _phantom: PhantomData }
+    }
+
+    pub const fn bits(self) -> u64 {
+        self.bits
+    }
+
+    pub fn insert(&mut self, action: A) {
+        self.bits |= 1 << action.index();
+    }
+
+    pub fn remove(&mut self, action: A) {
+        self.bits &= !(1 << action.index());
+    }
+
+    pub fn contains(self, action: A) -> bool {
+        self.bits & (1 << action.index()) != 0
+    }
+
+    pub fn union(self, other: Self) -> Self {
+        Self { bits: self.bits | other.bits, _phantom: PhantomData }
+    }
+
+    pub const fn len(self) -> usize {
+        self.bits.count_ones() as usize
+    }
+
+    pub const fn is_empty(self) -> bool {
+        self.bits == 0
+    }
+
+    /// The actions in the set, in the order of [`Action::ALL`]
+    pub fn iter(self) -> impl Iterator<Item = A> {
+        A::ALL.iter().copied().filter(move |&a| self.contains(a))
+    }
+}
+
+impl<A: Action> Default for ActionSet<A> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<A: Action> std::fmt::Debug for ActionSet<A> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_set().entries(self.iter().map(|a| a.to_string())).finish()
+    }
+}
+
+impl<A: Action> FromIterator<A> for ActionSet<A> {
+    fn from_iter<T: IntoIterator<Item = A>>(iter: T) -> Self {
+        let mut set = Self::new();
+        iter.into_iter().for_each(|a| set.insert(a));
+        set
+    }
+}
+
 /// Number of turns on the most negative face, number of turns on the most positive face,
 /// and the axis on which the turns happen
 #[derive(Clone, Copy, Debug)]
@@ -70,6 +256,7 @@ impl FromStr for Move {
 
 impl Action for Move {
     const ALL: &'static [Self] = Self::ALL_.as_slice();
+    const TAG: &'static str = "move";
 
     fn inverse(&self) -> Self {
         let Move(axis, rot1, rot2) = self;
@@ -79,6 +266,13 @@ impl Action for Move {
     fn from_move(m: Move) -> Vec<Self> {
         vec![m]
     }
+
+    // Mirrors the layout of `Move::ALL_`. The neutral move has no index.
+    fn index(&self) -> usize {
+        let Move(axis, rot1, rot2) = *self;
+        assert!(rot1 != 0 || rot2 != 0, "the neutral move has no index");
+        rot1 as usize * 12 + rot2 as usize * 3 + axis as usize - 3
+    }
 }
 
 impl Move {
@@ -127,6 +321,33 @@ impl Move {
             (Some(m1), Some(m2))
         }
     }
+    /// The move seen in a mirror across the plane between the two faces of `axis`, so `R` becomes
+    /// `L'` across the L/R plane and `U` becomes `U'`. Turns on the mirrored axis swap faces and
+    /// every other turn changes direction.
+    pub fn mirrored(self, axis: Axis) -> Self {
+        let Move(own, rot1, rot2) = self;
+        if own == axis {
+            Move(own, rot2, rot1)
+        } else {
+            Move(own, (4 - rot1) % 4, (4 - rot2) % 4)
+        }
+    }
+
+    /// The same turn after the whole cube is turned by `rot`, i.e. the move of the faces `rot` takes
+    /// this move's faces to
+    pub fn reoriented(self, rot: Rotation) -> Self {
+        let Move(axis, rot1, rot2) = self;
+        let mut unit = [0; 3];
+        unit[axis as usize] = 1;
+        let moved = rot.rotate_vector(unit);
+        let new_axis = [Axis::X, Axis::Y, Axis::Z][moved.iter().position(|&c| c != 0).unwrap()];
+        if moved.iter().sum::<i8>() > 0 {
+            Move(new_axis, rot1, rot2)
+        } else {
+            // The axis now points the other way, so the faces swap and the turns reverse
+            Move(new_axis, (4 - rot2) % 4, (4 - rot1) % 4)
+        }
+    }
 }
 
 // Questioning my choices using enums here because Move is not so what's the point?
@@ -199,6 +420,7 @@ impl Display for Turn {
 
 impl Action for Turn {
     const ALL: &'static [Self] = Self::ALL_.as_slice();
+    const TAG: &'static str = "turn";
 
     fn inverse(&self) -> Self {
         match self {
@@ -280,10 +502,14 @@ impl Action for Turn {
             _ => unreachable!(),
         }
     }
+
+    fn index(&self) -> usize {
+        *self as usize
+    }
 }
 
 impl Turn {
-    const ALL_: [Self; 18] = [Self::L, Self::L2, Self::L3, Self::R, Self::R2, Self::R3, Self::U, Self::U2, Self::U3, Self::D, Self::D2, Self::D3, Self::F, Self::F2, Self::F3, Self::B, Self::B2, Self::B3];
+    const ALL_: [Self; 18] = [Self::L, Self::L2, Self::L3, Self::R, Self::R2, Self::R3, Self::F, Self::F2, Self::F3, Self::B, Self::B2, Self::B3, Self::D, Self::D2, Self::D3, Self::U, Self::U2, Self::U3];
 }
 
 #[derive(Copy, Clone, PartialEq, Eq, Debug)]
@@ -341,6 +567,7 @@ impl Display for QuarterTurn {
 
 impl Action for QuarterTurn {
     const ALL: &'static [Self] = Self::ALL_.as_slice();
+    const TAG: &'static str = "quarter_turn";
 
     fn inverse(&self) -> Self {
         match self {
@@ -421,8 +648,57 @@ impl Action for QuarterTurn {
             _ => unreachable!(),
         }
     }
+
+    fn index(&self) -> usize {
+        *self as usize
+    }
 }
 
 impl QuarterTurn {
     const ALL_: [Self; 12] = [Self::L, Self::L3, Self::R, Self::R3, Self::U, Self::U3, Self::D, Self::D3, Self::F, Self::F3, Self::B, Self::B3];
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn count_canonical<A: Action>(depth: usize) -> Vec<u64> {
+        fn recurse<A: Action>(state: Canonical, depth: usize, counts: &mut [u64]) {
+            counts[depth] += 1;
+            if depth + 1 < counts.len() {
+                state.successors::<A>().for_each(|(_, next)| recurse::<A>(next, depth + 1, counts));
+            }
+        }
+
+        let mut counts = vec![0; depth + 1];
+        recurse::<A>(Canonical::START, 0, &mut counts);
+        counts
+    }
+
+    #[test]
+    fn test_canonical_counts() {
+        // Number of canonical sequences of each length in the half turn, quarter turn and axial
+        // metrics
+        assert_eq!(count_canonical::<Turn>(4), [1, 18, 243, 3240, 43254]);
+        assert_eq!(count_canonical::<QuarterTurn>(4), [1, 12, 114, 1068, 10011]);
+        assert_eq!(count_canonical::<Move>(3), [1, 45, 1350, 40500]);
+    }
+
+    #[test]
+    #[should_panic(expected = "the neutral move has no index")]
+    fn test_neutral_move_index() {
+        Move(Axis::X, 0, 0).index();
+    }
+
+    #[test]
+    fn test_canonical_rules() {
+        use Turn::*;
+        assert!(Canonical::is_canonical(&[L, R3, U]));
+        assert!(!Canonical::is_canonical(&[R3, L, U]));
+        assert!(!Canonical::is_canonical(&[R, R2]));
+        assert!(!Canonical::is_canonical(&[U, D, U]));
+        assert!(Canonical::is_canonical(&[QuarterTurn::L, QuarterTurn::L, QuarterTurn::R3]));
+        assert!(!Canonical::is_canonical(&[QuarterTurn::L, QuarterTurn::L3]));
+        assert!(!Canonical::is_canonical(&[QuarterTurn::L3, QuarterTurn::L3]));
+    }
+}
@@ -26,35 +26,27 @@ enum Tab {
 }
 
 impl Tab {
-    fn inner(&self) -> (&Cube<Position>, &Vec<Move>) {
+    fn inner(&self) -> (&Cube<Position>, &[Move]) {
         match self {
-            Tab::Moves(Word { cube, actions, .. }) => (cube, actions),
-            Tab::Turns(Word { cube, actions, .. }) => (cube, actions),
-            Tab::QuarterTurns(Word { cube, actions, .. }) => (cube, actions),
+            Tab::Moves(word) => (word.cube(), word.moves()),
+            Tab::Turns(word) => (word.cube(), word.moves()),
+            Tab::QuarterTurns(word) => (word.cube(), word.moves()),
         }
     }
 
-    fn inner_mut(&mut self) -> (&mut Cube<Position>, &mut Vec<Move>) {
+    fn make_move(&mut self, m: Move) {
         match self {
-            Tab::Moves(Word { cube, actions, .. }) => (cube, actions),
-            Tab::Turns(Word { cube, actions, .. }) => (cube, actions),
-            Tab::QuarterTurns(Word { cube, actions, .. }) => (cube, actions),
+            Tab::Moves(word) => word.extend(Move::from_move(m)),
+            Tab::Turns(word) => word.extend(Turn::from_move(m)),
+            Tab::QuarterTurns(word) => word.extend(QuarterTurn::from_move(m)),
         }
     }
 
-    fn make_move(&mut self, m: Move) {
-        let (cube, actions) = self.inner_mut();
-        *cube = cube.clone().make_move(m);
-        actions.push(m);
-    }
-
     fn pop(&mut self) -> Option<Move> {
-        let (cube, actions) = self.inner_mut();
-        if let Some(last_move) = actions.pop() {
-            *cube = cube.clone().make_move(last_move.inverse());
-            Some(last_move)
-        } else {
-            None
+        match self {
+            Tab::Moves(word) => word.pop(),
+            Tab::Turns(word) => word.pop().map(Into::into),
+            Tab::QuarterTurns(word) => word.pop().map(Into::into),
         }
     }
 
@@ -190,17 +182,17 @@ fn handle_events(app: &mut App) -> io::Result<bool> {
                     // Change to moves
                     KeyCode::Char('m') => {
                         let (cube, actions) = app.active().inner();
-                        *app.active_mut() = Tab::Moves(Word::from_parts_unchecked(cube.clone(), actions.clone()));
+                        *app.active_mut() = Tab::Moves(Word::from_parts_unchecked(cube.clone(), actions.to_vec()));
                     }
                     // Change to turns
                     KeyCode::Char('t') => {
                         let (cube, actions) = app.active().inner();
-                        *app.active_mut() = Tab::Turns(Word::from_parts_unchecked(cube.clone(), actions.clone()));
+                        *app.active_mut() = Tab::Turns(Word::from_parts_unchecked(cube.clone(), actions.to_vec()));
                     }
                     // Change to quarter turns
                     KeyCode::Char('T') => {
                         let (cube, actions) = app.active().inner();
-                        *app.active_mut() = Tab::QuarterTurns(Word::from_parts_unchecked(cube.clone(), actions.clone()));
+                        *app.active_mut() = Tab::QuarterTurns(Word::from_parts_unchecked(cube.clone(), actions.to_vec()));
                     }
                     // Move one tab to the left, wrapping around
                     KeyCode::Left => {
@@ -2,14 +2,14 @@
 //! starting point for creating a training dataset for an agent.
 
 #![allow(private_bounds)]
-use std::{borrow::Borrow, cmp::PartialOrd, io, marker::PhantomData};
-
-use sled::{self, Db, Tree};
+use std::{borrow::Borrow, cmp::{Ordering, PartialOrd}, collections::BTreeMap, fmt, io, marker::PhantomData, ops::{Bound, RangeBounds}, sync::Arc};
 
 use crate::{
-    action::{Action, Move, QuarterTurn, Turn},
+    action::{Action, ActionSet, Move, QuarterTurn, Turn},
+    cube::{Cube, Position},
     cubelet::Rotation,
-    word::Word
+    store::{BookStore, MemoryStore, SledStore, Table},
+    word::{Solver, Word},
 };
 
 fn as_bytes<T>(slice: &[T]) -> &[u8] {
@@ -17,13 +17,19 @@ fn as_bytes<T>(slice: &[T]) -> &[u8] {
     unsafe { std::slice::from_raw_parts(ptr.cast(), std::mem::size_of::<T>()) }
 }
 
-pub trait Int: PartialOrd + Copy {
+pub trait Int: PartialOrd + Copy + Into<u64> {
+    /// The number of bytes in `ToBytes`
+    const BYTES: usize;
+    /// A name for the type that will not change between releases, stored in a book's metadata
+    const TAG: &'static str;
     type ToBytes: Borrow<[u8]>;
     fn to_bytes(&self) -> Self::ToBytes;
     fn from_bytes(bytes: &[u8]) -> Self;
 }
 
 impl Int for u8 {
+    const TAG: &'static str = "u8";
+    const BYTES: usize = 1;
     type ToBytes = [u8; 1];
 
     fn to_bytes(&self) -> Self::ToBytes {
@@ -37,6 +43,8 @@ impl Int for u8 {
 }
 
 impl Int for u16 {
+    const TAG: &'static str = "u16";
+    const BYTES: usize = 2;
     type ToBytes = [u8; 2];
 
     fn to_bytes(&self) -> Self::ToBytes {
@@ -49,6 +57,8 @@ impl Int for u16 {
 }
 
 impl Int for u32 {
+    const TAG: &'static str = "u32";
+    const BYTES: usize = 4;
     type ToBytes = [u8; 4];
 
     fn to_bytes(&self) -> Self::ToBytes {
@@ -60,115 +70,1002 @@ impl Int for u32 {
     }
 }
 
-// I think we always store the cube as the key, followed by the depth (u8, u16, u32), followed by
-// the word (which has a variable length). There will be a special entry that records the format of
-// the Book. On opening an existing Book, it checks the data format and returns an error if the
-// format does not match the generics in the tree.
+// The cube is stored as the key. The value is the depth (u8, u16, u32), optionally followed by a
+// flags byte saying which of the best actions (a u64 mask over A::ALL) and an optimal solution (the
+// packed actions) come after it. The format of the Book is recorded in a separate metadata table. On
+// opening an existing Book, it upgrades the metadata if it is from an older version, checks the data
+// format and returns an error if the format does not match the generics in the table. The tables
+// live in any BookStore, sled by default.
 #[derive(Clone)]
 pub struct Book<Depth = u16, Action = Turn> {
-    // Db struct included to have access to the size_on_disk method
-    db: Db,
-    inner: Tree,
-    // Depth: "this_books_depth_type"
-    // Action: "this_books_action_type"
+    pub(crate) store: Arc<dyn BookStore>,
+    policy: MergePolicy<Depth>,
     _phantom: PhantomData<(Depth, Action)>,
 }
 
-const DEPTH_ENTRY: &[u8] = b"this_books_depth_type";
-const ACTION_ENTRY: &[u8] = b"this_books_action_type";
+/// The version of the way books are laid out. Books written by older versions of the crate are
+/// upgraded when they are opened.
+pub const FORMAT_VERSION: u32 = 2;
+
+// Keys of the metadata table
+const VERSION_KEY: &[u8] = b"format_version";
+const DEPTH_TYPE_KEY: &[u8] = b"depth_type";
+const ACTION_TYPE_KEY: &[u8] = b"action_type";
+const KEY_ENCODING_KEY: &[u8] = b"key_encoding";
+const POLICY_KEY: &[u8] = b"merge_policy";
+const GENERATORS_KEY: &[u8] = b"generators";
+const EXPLORED_DEPTH_KEY: &[u8] = b"explored_depth";
+const PARAMETERS_KEY: &[u8] = b"parameters";
+// Followed by the depth as a big endian u64, so the counts are in order
+const COUNT_PREFIX: &[u8] = b"count/";
+// Followed by the number of best actions the same way, counting only cubes with best actions
+const BRANCHING_PREFIX: &[u8] = b"branching/";
+
+// Every key is the 20 rotations of a cube packed at 5 bits each
+const KEY_ENCODING: &str = "rotations_5bit";
+
+// Version 0 kept the format in the entries table itself, using `std::any::type_name`
+const LEGACY_DEPTH_ENTRY: &[u8] = b"this_books_depth_type";
+const LEGACY_ACTION_ENTRY: &[u8] = b"this_books_action_type";
+const LEGACY_POLICY_ENTRY: &[u8] = b"this_books_merge_policy";
+
+/// Everything recorded about a book apart from its cubes
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Metadata {
+    pub version: u32,
+    /// [`Int::TAG`] of the depth type
+    pub depth_type: String,
+    /// [`Action::TAG`] of the action type
+    pub action_type: String,
+    pub key_encoding: String,
+    pub merge_policy: String,
+    /// The actions of the action type in the order of their indices, which stored best actions
+    /// and solutions are written in terms of
+    pub generators: String,
+    /// Every cube this close to solved is in the book, see [`Book::set_explored_depth`]
+    pub explored_depth: Option<u64>,
+    /// How the book was made, see [`Book::set_parameters`]
+    pub parameters: Option<String>,
+    /// The number of cubes at each depth
+    pub depth_counts: BTreeMap<u64, u64>,
+}
+
+/// What [`Book::stats`] reports about a book
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Stats {
+    /// The number of cubes at each depth
+    pub depth_counts: BTreeMap<u64, u64>,
+    /// The number of cubes
+    pub total: u64,
+    /// The number of cubes with each number of best actions, leaving out cubes whose best actions
+    /// were never recorded
+    pub branching_counts: BTreeMap<u64, u64>,
+    pub explored_depth: Option<u64>,
+    /// The number of bytes the book takes up on disk, 0 for books only held in memory
+    pub size_on_disk: u64,
+}
+
+impl Stats {
+    pub fn max_depth(&self) -> Option<u64> {
+        self.depth_counts.keys().next_back().copied()
+    }
+
+    pub fn mean_depth(&self) -> Option<f64> {
+        mean(&self.depth_counts)
+    }
+
+    /// The mean number of best actions of the cubes that have them recorded
+    pub fn mean_branching(&self) -> Option<f64> {
+        mean(&self.branching_counts)
+    }
+}
+
+fn mean(counts: &BTreeMap<u64, u64>) -> Option<f64> {
+    let total: u64 = counts.values().sum();
+    let sum: u64 = counts.iter().map(|(n, count)| n * count).sum();
+    (total > 0).then(|| sum as f64 / total as f64)
+}
+
+/// A table of the depth counts with a bar for each, scaled so the largest count is 40 wide
+impl fmt::Display for Stats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let most = self.depth_counts.values().copied().max().unwrap_or(0);
+        writeln!(f, "{:>5}  {:>12}", "depth", "cubes")?;
+        for (depth, &count) in &self.depth_counts {
+            let bar = (count * 40).div_ceil(most.max(1)) as usize;
+            writeln!(f, "{depth:>5}  {count:>12}  {}", "#".repeat(bar))?;
+        }
+        write!(f, "{:>5}  {:>12}", "total", self.total)?;
+        if let Some(mean) = self.mean_branching() {
+            write!(f, "\nmean best actions {mean:.3}")?;
+        }
+        Ok(())
+    }
+}
 
-impl<D: Int, A: Packable + Into<Move>> Book<D, A> {
+/// How [`Book::insert`] combines the depth already recorded for a cube with a new one. The policy
+/// is recorded in the book so that the same one is used every time the book is opened.
+#[derive(Clone, Default)]
+pub enum MergePolicy<D> {
+    /// Keep the smaller depth, so finding a shorter way to a cube improves the book
+    #[default]
+    Min,
+    /// Keep the larger depth
+    Max,
+    /// Keep whichever depth was recorded first
+    FirstWrite,
+    /// Keep whichever depth was recorded last
+    LastWrite,
+    /// Call the function with the recorded depth and the new one, in that order. A closure can't be
+    /// stored in the book, so books made with one have to be opened with [`Book::open_with_policy`].
+    Custom(Arc<dyn Fn(D, D) -> D + Send + Sync>),
+}
+
+impl<D: Int> MergePolicy<D> {
+    fn merge(&self, current: D, new: D) -> D {
+        match self {
+            Self::Min => if new < current { new } else { current },
+            Self::Max => if current < new { new } else { current },
+            Self::FirstWrite => current,
+            Self::LastWrite => new,
+            Self::Custom(f) => f(current, new),
+        }
+    }
+
+    fn name(&self) -> &'static str {
+        match self {
+            Self::Min => "min",
+            Self::Max => "max",
+            Self::FirstWrite => "first_write",
+            Self::LastWrite => "last_write",
+            Self::Custom(_) => "custom",
+        }
+    }
+
+    fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "min" => Some(Self::Min),
+            "max" => Some(Self::Max),
+            "first_write" => Some(Self::FirstWrite),
+            "last_write" => Some(Self::LastWrite),
+            _ => None,
+        }
+    }
+}
+
+impl<D> fmt::Debug for MergePolicy<D> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Min => write!(f, "Min"),
+            Self::Max => write!(f, "Max"),
+            Self::FirstWrite => write!(f, "FirstWrite"),
+            Self::LastWrite => write!(f, "LastWrite"),
+            Self::Custom(_) => write!(f, "Custom(..)"),
+        }
+    }
+}
+
+impl<D: Int, A: Packable + Action> Book<D, A> {
+    /// Open an existing book with the merge policy it was created with
     pub fn open(file_path: &str) -> io::Result<Self> {
-        let db = sled::open(file_path)?;
-        if !db.was_recovered() {
+        Self::open_inner(file_path, None)
+    }
+
+    /// Open an existing book, checking that it was created with `policy`. This is the only way to
+    /// open a book made with [`MergePolicy::Custom`].
+    pub fn open_with_policy(file_path: &str, policy: MergePolicy<D>) -> io::Result<Self> {
+        Self::open_inner(file_path, Some(policy))
+    }
+
+    fn open_inner(file_path: &str, policy: Option<MergePolicy<D>>) -> io::Result<Self> {
+        let store = SledStore::open(file_path)?;
+        if !store.was_recovered() {
             let _ = std::fs::remove_dir_all(file_path);
             return Err(io::Error::new(io::ErrorKind::NotFound, file_path.to_owned()));
         }
+        Self::open_store(Arc::new(store), policy)
+    }
 
-        let inner = db.open_tree(b"book")?;
+    /// Open the book kept in `store` with the merge policy it was created with
+    pub fn open_in(store: impl BookStore + 'static) -> io::Result<Self> {
+        Self::open_in_with_policy_inner(store, None)
+    }
 
-        let depth_type = std::str::from_utf8(
-            inner.get(DEPTH_ENTRY)?
-                .ok_or(io::Error::new(io::ErrorKind::NotFound, "Opened book does not contain a depth type"))?
-                .as_ref()
-            )
-            .map_err(|_| io::Error::new(io::ErrorKind::InvalidData, "Invalid UTF-8 found in depth type entry"))?
-            .to_owned();
-        if std::str::from_utf8(depth_type.as_ref()).unwrap() != std::any::type_name::<D>() {
-            return Err(io::Error::new(
-                io::ErrorKind::InvalidData,
-                format!(
-                    "Opened book has a different depth type: expected {}, got {}",
-                    std::any::type_name::<D>(),
-                    depth_type
-                )
-            ))
-        }
-
-        let action_type = std::str::from_utf8(
-            inner.get(ACTION_ENTRY)?
-                .ok_or(io::Error::new(io::ErrorKind::NotFound, "Opened book does not contain an action type"))?
-                .as_ref()
-            )
-            .map_err(|_| io::Error::new(io::ErrorKind::InvalidData, "Invalid UTF-8 found in action type entry"))?
-            .to_owned();
-        if std::str::from_utf8(action_type.as_ref()).unwrap() != std::any::type_name::<A>() {
-            return Err(io::Error::new(
-                io::ErrorKind::InvalidData,
-                format!(
-                    "Opened book has a different action type: expected {}, got {}",
-                    std::any::type_name::<A>(),
-                    action_type
-                )
-            ))
+    /// Open the book kept in `store`, checking that it was created with `policy`
+    pub fn open_in_with_policy(store: impl BookStore + 'static, policy: MergePolicy<D>) -> io::Result<Self> {
+        Self::open_in_with_policy_inner(store, Some(policy))
+    }
+
+    fn open_in_with_policy_inner(store: impl BookStore + 'static, policy: Option<MergePolicy<D>>) -> io::Result<Self> {
+        if store.is_empty() {
+            return Err(io::Error::new(io::ErrorKind::NotFound, "Store does not contain a book"));
         }
+        Self::open_store(Arc::new(store), policy)
+    }
 
-        Ok(Book { db, inner, _phantom: PhantomData })
+    fn open_store(store: Arc<dyn BookStore>, policy: Option<MergePolicy<D>>) -> io::Result<Self> {
+        let meta = &*store;
+        upgrade::<D, A>(meta)?;
+        check_format::<D, A>(meta)?;
+
+        let recorded = read_string(meta, POLICY_KEY, "merge policy")?;
+        let policy = match (recorded, policy) {
+            // Books from before the merge policy was recorded
+            (None, policy) => {
+                let policy = policy.unwrap_or_default();
+                meta.insert(Table::Meta, POLICY_KEY, policy.name().as_bytes())?;
+                policy
+            }
+            (Some(name), None) => MergePolicy::from_name(&name).ok_or(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("Opened book uses the {name} merge policy, which has to be given to open_with_policy")
+            ))?,
+            (Some(name), Some(policy)) if name == policy.name() => policy,
+            (Some(name), Some(policy)) => return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("Opened book has a different merge policy: expected {}, got {}", policy.name(), name)
+            )),
+        };
+
+        Ok(Book { store, policy, _phantom: PhantomData })
     }
 
+    /// Create a new book that keeps the smallest depth found for each cube
     pub fn create(file_path: &str) -> io::Result<Self> {
-        let db = sled::open(file_path)?;
-        if db.was_recovered() { return Err(io::Error::new(io::ErrorKind::AlreadyExists, file_path.to_owned())); }
+        Self::create_with_policy(file_path, MergePolicy::default())
+    }
+
+    pub fn create_with_policy(file_path: &str, policy: MergePolicy<D>) -> io::Result<Self> {
+        let store = SledStore::open(file_path)?;
+        if store.was_recovered() { return Err(io::Error::new(io::ErrorKind::AlreadyExists, file_path.to_owned())); }
+        Self::create_store(Arc::new(store), policy)
+    }
 
-        let inner = db.open_tree(b"book")?;
-        inner.insert(DEPTH_ENTRY, std::any::type_name::<D>())?;
-        inner.insert(ACTION_ENTRY, std::any::type_name::<A>())?;
+    /// Create a new book in `store`, which has to be empty
+    pub fn create_in(store: impl BookStore + 'static) -> io::Result<Self> {
+        Self::create_in_with_policy(store, MergePolicy::default())
+    }
+
+    pub fn create_in_with_policy(store: impl BookStore + 'static, policy: MergePolicy<D>) -> io::Result<Self> {
+        if !store.is_empty() {
+            return Err(io::Error::new(io::ErrorKind::AlreadyExists, "Store already contains a book"));
+        }
+        Self::create_store(Arc::new(store), policy)
+    }
+
+    /// Create a new book held only in memory, which is gone once every clone of it is dropped
+    pub fn in_memory() -> Self {
+        Self::create_in(MemoryStore::new()).unwrap()
+    }
+
+    fn create_store(store: Arc<dyn BookStore>, policy: MergePolicy<D>) -> io::Result<Self> {
+        let meta = |key, value: &[u8]| store.insert(Table::Meta, key, value);
+        meta(VERSION_KEY, &(FORMAT_VERSION as u64).to_le_bytes())?;
+        meta(DEPTH_TYPE_KEY, D::TAG.as_bytes())?;
+        meta(ACTION_TYPE_KEY, A::TAG.as_bytes())?;
+        meta(KEY_ENCODING_KEY, KEY_ENCODING.as_bytes())?;
+        meta(GENERATORS_KEY, generators::<A>().as_bytes())?;
+        meta(POLICY_KEY, policy.name().as_bytes())?;
+
+        Ok(Book { store, policy, _phantom: PhantomData })
+    }
+
+    /// Make sure everything written to the book so far is on disk
+    pub fn flush(&self) -> io::Result<()> {
+        self.store.flush()
+    }
+
+    pub fn policy(&self) -> &MergePolicy<D> {
+        &self.policy
+    }
+
+    /// Everything recorded about the book apart from its cubes
+    pub fn metadata(&self) -> io::Result<Metadata> {
+        read_metadata(&*self.store)
+    }
+
+    /// The number of cubes at each depth, kept up to date by every insert
+    pub fn depth_counts(&self) -> io::Result<BTreeMap<u64, u64>> {
+        read_depth_counts(&*self.store)
+    }
 
-        Ok(Book { db, inner, _phantom: PhantomData })
+    /// Record that every cube at most `depth` from solved is in the book, e.g. after a complete
+    /// breadth-first search. Cubes the book does not have are then known to be further away.
+    pub fn set_explored_depth(&self, depth: u64) -> io::Result<()> {
+        self.store.insert(Table::Meta, EXPLORED_DEPTH_KEY, &depth.to_le_bytes())
+    }
+
+    /// Record how the book was made, in whatever form suits the caller
+    pub fn set_parameters(&self, parameters: &str) -> io::Result<()> {
+        self.store.insert(Table::Meta, PARAMETERS_KEY, parameters.as_bytes())
     }
 
     pub fn insert(&self, word: Word<A>, depth: D) -> io::Result<Option<D>> {
         // TODO: pack if packed; is packed part of the generics or is it a runtime setting?
         // Probably the generics, right?
         // let key = as_bytes(&pair.current_state().cubelets);
-        let key = pack(&word.cube.cubelets);
-
-        let update_fn = |slice: Option<&[u8]>| -> Option<Vec<u8>> {
-        let depth = if let Some(slice) = slice {
-                let current = D::from_bytes(slice);
-                if current < depth {
-                    depth
-                } else {
-                    current
-                }
-            } else {
-                depth
+        let key = pack(&word.cube().cubelets);
+        self.insert_value(key, Value { depth, best: None, solution: None })
+    }
+
+    fn insert_value(&self, key: Vec<u8>, new: Value<D>) -> io::Result<Option<D>> {
+        let mut merged = None;
+        let mut update_fn = |slice: Option<&[u8]>| -> Option<Vec<u8>> {
+            let value = match slice {
+                Some(slice) => Value::decode(slice).merge(new.clone(), &self.policy),
+                None => new.clone(),
             };
 
-            Some(depth.to_bytes().borrow().to_vec())
+            let bytes = value.encode();
+            merged = Some(value);
+            Some(bytes)
+        };
+
+        let previous = self.store.update(Table::Entries, &key, &mut update_fn)?;
+        let previous = previous.map(|bytes| Value::<D>::decode(&bytes));
+        let merged = merged.expect("the store calls the update function");
+
+        // Keep the depth and branching counts in step
+        let recount = |prefix, before: Option<u64>, after: Option<u64>| -> io::Result<()> {
+            if before != after {
+                if let Some(before) = before {
+                    add_to_count(&*self.store, prefix, before, -1)?;
+                }
+                if let Some(after) = after {
+                    add_to_count(&*self.store, prefix, after, 1)?;
+                }
+            }
+            Ok(())
         };
+        let branching = |value: &Value<D>| value.best.map(|best| best.count_ones() as u64);
+        recount(COUNT_PREFIX, previous.as_ref().map(|value| value.depth.into()), Some(merged.depth.into()))?;
+        recount(BRANCHING_PREFIX, previous.as_ref().and_then(branching), branching(&merged))?;
 
-        let previous = self.inner.fetch_and_update(key, update_fn)?;
+        Ok(previous.map(|value| value.depth))
+    }
 
-        Ok(previous.map(|ivec| D::from_bytes(ivec.as_ref())))
+    /// The depth histogram and everything else worth knowing about the book's contents. Every
+    /// count is kept up to date by inserts, so this doesn't read the cubes.
+    pub fn stats(&self) -> io::Result<Stats> {
+        let depth_counts = self.depth_counts()?;
+        Ok(Stats {
+            total: depth_counts.values().sum(),
+            depth_counts,
+            branching_counts: read_counts(&*self.store, BRANCHING_PREFIX)?,
+            explored_depth: read_u64(&*self.store, EXPLORED_DEPTH_KEY)?,
+            size_on_disk: self.size()?,
+        })
     }
 
+    /// The number of bytes the book takes up on disk
     pub fn size(&self) -> io::Result<u64> {
-        Ok(self.db.size_on_disk()?)
+        self.store.size_on_disk()
     }
+
+    /// The depth recorded for `cube`, if the book has it
+    pub fn get(&self, cube: &Cube<Position>) -> io::Result<Option<D>> {
+        let key = pack(&cube.cubelets);
+        Ok(self.store.get(Table::Entries, &key)?.map(|bytes| D::from_bytes(&bytes[..D::BYTES])))
+    }
+
+    pub fn contains(&self, cube: &Cube<Position>) -> io::Result<bool> {
+        Ok(self.store.get(Table::Entries, &pack(&cube.cubelets))?.is_some())
+    }
+
+    /// The number of cubes in the book
+    pub fn len(&self) -> usize {
+        self.store.len(Table::Entries)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Every cube in the book with its depth, in the order of their packed keys
+    pub fn iter(&self) -> impl Iterator<Item = io::Result<(Cube<Position>, D)>> + '_ {
+        self.store.iter(Table::Entries).filter_map(decode_entry)
+    }
+
+    /// The cubes whose packed keys fall in `range`, with their depths
+    pub fn range<R: RangeBounds<Cube<Position>>>(&self, range: R) -> impl Iterator<Item = io::Result<(Cube<Position>, D)>> + '_ {
+        let key = |cube: &Cube<Position>| pack(&cube.cubelets);
+        let start = range.start_bound().map(key);
+        let end = range.end_bound().map(key);
+        self.store.range(Table::Entries, start.as_ref().map(Vec::as_slice), end.as_ref().map(Vec::as_slice))
+            .filter_map(decode_entry)
+    }
+
+    /// The cubes whose first cubelets (in the order of [`crate::cube::index`]) are `prefix`, with
+    /// their depths. The whole bytes of the packed prefix narrow the scan and the rest are checked
+    /// after decoding.
+    pub fn scan_prefix(&self, prefix: &[Rotation]) -> impl Iterator<Item = io::Result<(Cube<Position>, D)>> + '_ {
+        let whole_bytes = prefix.len() * Rotation::PACKED_BITS / 8;
+        let packed = pack(prefix)[..whole_bytes].to_vec();
+        let prefix = prefix.to_vec();
+        self.store.range(Table::Entries, Bound::Included(&packed), Bound::Unbounded)
+            .take_while(move |entry| entry.as_ref().map_or(true, |(key, _)| key.starts_with(&packed)))
+            .filter_map(decode_entry)
+            .filter(move |entry| match entry {
+                Ok((cube, _)) => cube.cubelets.starts_with(&prefix),
+                Err(_) => true,
+            })
+    }
+}
+
+// 20 cubelets at 5 bits each
+pub(crate) const KEY_LEN: usize = (20 * Rotation::PACKED_BITS).div_ceil(8);
+
+/// Decode a key-value pair from the tree, skipping anything that isn't a cube
+fn decode_entry<D: Int>(entry: io::Result<(Vec<u8>, Vec<u8>)>) -> Option<io::Result<(Cube<Position>, D)>> {
+    let (key, value) = match entry {
+        Ok(pair) => pair,
+        Err(e) => return Some(Err(e)),
+    };
+    let cube = decode_key(&key)?;
+    Some(cube.map(|cube| (cube, D::from_bytes(&value[..D::BYTES]))))
+}
+
+/// Decode a key from the tree, or `None` if it isn't a cube
+fn decode_key(key: &[u8]) -> Option<io::Result<Cube<Position>>> {
+    if key.len() != KEY_LEN {
+        return None;
+    }
+
+    let cubelets: Vec<Rotation> = unpack(key);
+    Some(cubelets.try_into()
+        .map(Cube::new)
+        .map_err(|_| io::Error::new(io::ErrorKind::InvalidData, "Book key does not hold 20 cubelets")))
+}
+
+/// The actions of `A` in index order, separated by spaces
+fn generators<A: Action>() -> String {
+    A::ALL.iter().map(|a| a.to_string()).collect::<Vec<_>>().join(" ")
+}
+
+fn read_string(store: &dyn BookStore, key: &[u8], what: &str) -> io::Result<Option<String>> {
+    read_string_from(store, Table::Meta, key, what)
 }
 
-trait Packable: Copy {
+fn read_string_from(store: &dyn BookStore, table: Table, key: &[u8], what: &str) -> io::Result<Option<String>> {
+    store.get(table, key)?
+        .map(String::from_utf8)
+        .transpose()
+        .map_err(|_| io::Error::new(io::ErrorKind::InvalidData, format!("Invalid UTF-8 found in {what} entry")))
+}
+
+fn read_u64(store: &dyn BookStore, key: &[u8]) -> io::Result<Option<u64>> {
+    store.get(Table::Meta, key)?
+        .map(|bytes| bytes.as_slice().try_into().map(u64::from_le_bytes))
+        .transpose()
+        .map_err(|_| io::Error::new(io::ErrorKind::InvalidData, "Book metadata entry is not a u64"))
+}
+
+/// Check that the format recorded in `meta` is the one used for `D` and `A`
+pub(crate) fn check_format<D: Int, A: Action>(meta: &dyn BookStore) -> io::Result<()> {
+    check_tag(meta, DEPTH_TYPE_KEY, "depth type", D::TAG)?;
+    check_tag(meta, ACTION_TYPE_KEY, "action type", A::TAG)?;
+    check_tag(meta, KEY_ENCODING_KEY, "key encoding", KEY_ENCODING)?;
+    check_tag(meta, GENERATORS_KEY, "generator set", &generators::<A>())
+}
+
+pub(crate) fn read_metadata(meta: &dyn BookStore) -> io::Result<Metadata> {
+    let string = |key, what| read_string(meta, key, what)?
+        .ok_or(io::Error::new(io::ErrorKind::NotFound, format!("Book does not contain a {what}")));
+    Ok(Metadata {
+        version: read_u64(meta, VERSION_KEY)?.unwrap_or(0) as u32,
+        depth_type: string(DEPTH_TYPE_KEY, "depth type")?,
+        action_type: string(ACTION_TYPE_KEY, "action type")?,
+        key_encoding: string(KEY_ENCODING_KEY, "key encoding")?,
+        merge_policy: string(POLICY_KEY, "merge policy")?,
+        generators: string(GENERATORS_KEY, "generator set")?,
+        explored_depth: read_u64(meta, EXPLORED_DEPTH_KEY)?,
+        parameters: read_string(meta, PARAMETERS_KEY, "parameters")?,
+        depth_counts: read_depth_counts(meta)?,
+    })
+}
+
+fn read_depth_counts(meta: &dyn BookStore) -> io::Result<BTreeMap<u64, u64>> {
+    read_counts(meta, COUNT_PREFIX)
+}
+
+fn read_counts(meta: &dyn BookStore, prefix: &[u8]) -> io::Result<BTreeMap<u64, u64>> {
+    meta.scan_prefix(Table::Meta, prefix)
+        .map(|entry| {
+            let (key, value) = entry?;
+            let n = u64::from_be_bytes(key[prefix.len()..].try_into().unwrap());
+            Ok((n, u64::from_le_bytes(value.as_slice().try_into().unwrap())))
+        })
+        .filter(|entry| !matches!(entry, Ok((_, 0))))
+        .collect()
+}
+
+fn check_tag(meta: &dyn BookStore, key: &[u8], what: &str, expected: &str) -> io::Result<()> {
+    let tag = read_string(meta, key, what)?
+        .ok_or(io::Error::new(io::ErrorKind::NotFound, format!("Opened book does not contain a {what}")))?;
+    if tag != expected {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("Opened book has a different {what}: expected {expected}, got {tag}")
+        ));
+    }
+    Ok(())
+}
+
+fn add_to_count(meta: &dyn BookStore, prefix: &[u8], n: u64, delta: i64) -> io::Result<()> {
+    meta.update(Table::Meta, &[prefix, &n.to_be_bytes()].concat(), &mut |count| {
+        let count = count.map_or(0, |c| u64::from_le_bytes(c.try_into().unwrap()));
+        Some(count.saturating_add_signed(delta).to_le_bytes().to_vec())
+    })?;
+    Ok(())
+}
+
+/// Bring the metadata of a book written by an older version of the crate up to
+/// [`FORMAT_VERSION`], one version at a time, so that books of any age can still be opened.
+/// Every step is worked out before anything is written, so a book that can't be opened as `D` and
+/// `A` is left as it was.
+fn upgrade<D: Int, A: Packable + Action>(store: &dyn BookStore) -> io::Result<()> {
+    let version = read_u64(store, VERSION_KEY)?.unwrap_or(0) as u32;
+    if version > FORMAT_VERSION {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("Opened book has format version {version}, which is newer than this crate's {FORMAT_VERSION}")
+        ));
+    }
+    if version == FORMAT_VERSION {
+        return Ok(());
+    }
+    if version > 0 {
+        // The steps below decode entries as `D`, so the format has to be checked first
+        check_format::<D, A>(store)?;
+    }
+
+    let mut changes = Vec::new();
+    for version in version..FORMAT_VERSION {
+        match version {
+            0 => changes.extend(upgrade_from_type_names::<D, A>(store)?),
+            1 => changes.extend(upgrade_branching_counts::<D>(store)?),
+            _ => unreachable!(),
+        }
+    }
+    for (table, key, value) in changes {
+        match value {
+            Some(value) => store.insert(table, &key, &value)?,
+            None => store.remove(table, &key)?,
+        }
+    }
+    store.insert(Table::Meta, VERSION_KEY, &(FORMAT_VERSION as u64).to_le_bytes())
+}
+
+/// A write made by an upgrade: the value to set a key to, or `None` to remove it
+type Change = (Table, Vec<u8>, Option<Vec<u8>>);
+
+/// Version 0 to 1: move the format out of the entries table, turning `std::any::type_name`s into tags,
+/// and count the cubes at each depth
+fn upgrade_from_type_names<D: Int, A: Packable + Action>(store: &dyn BookStore) -> io::Result<Vec<Change>> {
+    let check_type_name = |key, what: &str, expected: &str| -> io::Result<()> {
+        let name = read_string_from(store, Table::Entries, key, what)?
+            .ok_or(io::Error::new(io::ErrorKind::NotFound, format!("Opened book does not contain a {what}")))?;
+        let tag = tag_from_type_name(&name);
+        if tag != expected {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("Opened book has a different {what}: expected {expected}, got {tag}")
+            ));
+        }
+        Ok(())
+    };
+    check_type_name(LEGACY_DEPTH_ENTRY, "depth type", D::TAG)?;
+    check_type_name(LEGACY_ACTION_ENTRY, "action type", A::TAG)?;
+
+    let meta = |key: &[u8], value: &[u8]| (Table::Meta, key.to_vec(), Some(value.to_vec()));
+    let mut changes = vec![
+        meta(DEPTH_TYPE_KEY, D::TAG.as_bytes()),
+        meta(ACTION_TYPE_KEY, A::TAG.as_bytes()),
+        meta(KEY_ENCODING_KEY, KEY_ENCODING.as_bytes()),
+        // Version 0 books only ever stored depths, so they can't disagree with the current actions
+        meta(GENERATORS_KEY, generators::<A>().as_bytes()),
+    ];
+    if let Some(policy) = store.get(Table::Entries, LEGACY_POLICY_ENTRY)? {
+        changes.push(meta(POLICY_KEY, &policy));
+    }
+
+    let mut counts = BTreeMap::<u64, u64>::new();
+    for entry in store.iter(Table::Entries).filter_map(decode_entry::<D>) {
+        let (_, depth) = entry?;
+        *counts.entry(depth.into()).or_default() += 1;
+    }
+    changes.extend(count_changes(COUNT_PREFIX, counts));
+
+    changes.extend([LEGACY_DEPTH_ENTRY, LEGACY_ACTION_ENTRY, LEGACY_POLICY_ENTRY]
+        .map(|key| (Table::Entries, key.to_vec(), None)));
+    Ok(changes)
+}
+
+/// Version 1 to 2: count the cubes by their number of best actions
+fn upgrade_branching_counts<D: Int>(store: &dyn BookStore) -> io::Result<Vec<Change>> {
+    let mut counts = BTreeMap::<u64, u64>::new();
+    for entry in store.iter(Table::Entries) {
+        let (key, value) = entry?;
+        if key.len() != KEY_LEN {
+            continue;
+        }
+        if let Some(best) = Value::<D>::decode(&value).best {
+            *counts.entry(best.count_ones() as u64).or_default() += 1;
+        }
+    }
+    Ok(count_changes(BRANCHING_PREFIX, counts).collect())
+}
+
+/// The writes that set the counts under `prefix`, in the layout used by [`add_to_count`]
+fn count_changes(prefix: &[u8], counts: BTreeMap<u64, u64>) -> impl Iterator<Item = Change> + '_ {
+    counts.into_iter()
+        .map(move |(n, count)| (Table::Meta, [prefix, &n.to_be_bytes()].concat(), Some(count.to_le_bytes().to_vec())))
+}
+
+/// The tag for a type from its `std::any::type_name`, e.g. `quarter_turn` for
+/// `rubiks::action::QuarterTurn`. Only the last path segment is used, since the rest depends on
+/// where the crate and the type live.
+fn tag_from_type_name(name: &str) -> String {
+    let last = name.rsplit("::").next().unwrap_or(name);
+    let mut tag = String::new();
+    for (i, c) in last.chars().enumerate() {
+        if c.is_uppercase() && i > 0 {
+            tag.push('_');
+        }
+        tag.push(c.to_ascii_lowercase());
+    }
+    tag
+}
+
+impl<D: Int, A: Packable + Action> Book<D, A> {
+    /// Record everything known about `cube`. The depths are merged with the book's
+    /// [`MergePolicy`]. When both depths are kept the best actions are combined, otherwise the
+    /// extras of whichever depth is kept stay with it.
+    pub fn insert_entry(&self, cube: &Cube<Position>, entry: Entry<D, A>) -> io::Result<Option<D>> {
+        let value = Value {
+            depth: entry.depth,
+            best: (!entry.best.is_empty()).then(|| entry.best.bits()),
+            solution: entry.solution.map(|actions| pack(&actions)),
+        };
+        self.insert_value(pack(&cube.cubelets), value)
+    }
+
+    /// Everything the book knows about `cube`
+    pub fn get_entry(&self, cube: &Cube<Position>) -> io::Result<Option<Entry<D, A>>> {
+        let Some(bytes) = self.store.get(Table::Entries, &pack(&cube.cubelets))? else { return Ok(None) };
+        Ok(Some(Value::<D>::decode(&bytes).into_entry()))
+    }
+
+    /// Every cube in the book with everything recorded about it, in the order of their packed keys
+    pub fn entries(&self) -> impl Iterator<Item = io::Result<(Cube<Position>, Entry<D, A>)>> + '_ {
+        self.store.iter(Table::Entries).filter_map(|entry| {
+            let (key, value) = match entry {
+                Ok(pair) => pair,
+                Err(e) => return Some(Err(e)),
+            };
+            let cube = decode_key(&key)?;
+            Some(cube.map(|cube| (cube, Value::<D>::decode(&value).into_entry())))
+        })
+    }
+
+    /// Insert every cube of `other` into this book, merging depths with this book's
+    /// [`MergePolicy`] and keeping the best actions and solutions the way [`Book::insert_entry`]
+    /// does. Returns the number of cubes read from `other`. Fails before anything is written if the
+    /// books were made with different formats or generators.
+    ///
+    /// If both books record an explored depth, the larger one is kept, since every cube that close
+    /// to solved is in one of the books.
+    pub fn merge_from(&self, other: &Self) -> io::Result<u64> {
+        let ours = self.metadata()?;
+        let theirs = other.metadata()?;
+        let fields = [
+            ("format version", ours.version.to_string(), theirs.version.to_string()),
+            ("depth type", ours.depth_type, theirs.depth_type),
+            ("action type", ours.action_type, theirs.action_type),
+            ("key encoding", ours.key_encoding, theirs.key_encoding),
+            ("generator set", ours.generators, theirs.generators),
+        ];
+        for (what, ours, theirs) in fields {
+            if ours != theirs {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("Books have different {what}s: {ours} and {theirs}")
+                ));
+            }
+        }
+
+        let mut count = 0;
+        for entry in other.store.iter(Table::Entries) {
+            let (key, value) = entry?;
+            if key.len() != KEY_LEN {
+                continue;
+            }
+            self.insert_value(key, Value::decode(&value))?;
+            count += 1;
+        }
+
+        if let Some(depth) = theirs.explored_depth {
+            if ours.explored_depth.is_none_or(|ours| ours < depth) {
+                self.set_explored_depth(depth)?;
+            }
+        }
+        Ok(count)
+    }
+
+    /// The cubes that only one of the books has and those whose depths disagree, in the order of
+    /// their packed keys. Neither book is held in memory, so this works for books of any size.
+    pub fn diff<'a>(a: &'a Self, b: &'a Self) -> impl Iterator<Item = io::Result<Difference<D>>> + 'a {
+        let mut a = a.store.iter(Table::Entries).peekable();
+        let mut b = b.store.iter(Table::Entries).peekable();
+        std::iter::from_fn(move || loop {
+            let next = match (a.peek(), b.peek()) {
+                (Some(Err(_)), _) => return a.next().map(|e| Err(e.unwrap_err())),
+                (_, Some(Err(_))) => return b.next().map(|e| Err(e.unwrap_err())),
+                (None, None) => return None,
+                (Some(Ok((ka, _))), Some(Ok((kb, _)))) => ka.cmp(kb),
+                (Some(_), None) => Ordering::Less,
+                (None, Some(_)) => Ordering::Greater,
+            };
+            let difference = match next {
+                Ordering::Less => decode_entry::<D>(a.next().unwrap())
+                    .map(|entry| entry.map(|(cube, depth)| Difference::OnlyInFirst(cube, depth))),
+                Ordering::Greater => decode_entry::<D>(b.next().unwrap())
+                    .map(|entry| entry.map(|(cube, depth)| Difference::OnlyInSecond(cube, depth))),
+                Ordering::Equal => {
+                    let (key, va) = a.next().unwrap().unwrap();
+                    let (_, vb) = b.next().unwrap().unwrap();
+                    let (da, db) = (D::from_bytes(&va[..D::BYTES]), D::from_bytes(&vb[..D::BYTES]));
+                    if da == db {
+                        continue;
+                    }
+                    decode_key(&key).map(|cube| cube.map(|cube| Difference::Depth(cube, da, db)))
+                }
+            };
+            // Anything that isn't a cube is skipped
+            if difference.is_some() {
+                return difference;
+            }
+        })
+    }
+
+    /// The actions that start an optimal solution of `cube`. The set is empty if the book has the
+    /// cube but they were never recorded.
+    pub fn best_actions(&self, cube: &Cube<Position>) -> io::Result<Option<ActionSet<A>>> {
+        Ok(self.get_entry(cube)?.map(|entry| entry.best))
+    }
+
+    /// The optimal solution of `cube` stored in the book, if there is one
+    pub fn stored_solution(&self, cube: &Cube<Position>) -> io::Result<Option<Word<A>>> {
+        Ok(self.get_entry(cube)?.and_then(|entry| entry.solution).map(|actions| {
+            let mut word = Word::new();
+            word.extend(actions);
+            word
+        }))
+    }
+
+    /// The actions from `cube` that lead to a cube one closer to solved, according to the depths
+    /// in the book. This is how the best actions of an entry are usually found.
+    pub fn actions_toward_solved(&self, cube: &Cube<Position>) -> io::Result<ActionSet<A>> {
+        let Some(depth) = self.get(cube)? else { return Ok(ActionSet::new()) };
+        let mut best = ActionSet::new();
+        for &a in A::ALL {
+            if let Some(d) = self.get(&cube.clone().make_move(a))? {
+                if d.into() + 1 == depth.into() {
+                    best.insert(a);
+                }
+            }
+        }
+        Ok(best)
+    }
+
+    /// Solve `cube` by following the book down to the solved state. Each step takes an action to the
+    /// neighbor with the smallest depth, as long as it is smaller than the current one, preferring
+    /// the recorded best actions on ties, and a stored solution is used as soon as one is found. If
+    /// the depths are distances this is an optimal solution.
+    ///
+    /// Fails with [`io::ErrorKind::NotFound`] if the book does not have `cube`, which usually means
+    /// it is further from solved than the book reaches, and with [`io::ErrorKind::InvalidData`] if
+    /// no neighbor on the way down has a smaller depth.
+    pub fn solve(&self, cube: &Cube<Position>) -> io::Result<Word<A>> {
+        let actions = match self.descend(cube)? {
+            Some(actions) => actions,
+            None if !self.contains(cube)? => return Err(io::Error::new(
+                io::ErrorKind::NotFound,
+                match read_u64(&*self.store, EXPLORED_DEPTH_KEY)? {
+                    Some(depth) => format!("Cube is not in the book, so it is more than {depth} from solved"),
+                    None => "Cube is not in the book, it may be further from solved than the book reaches".to_owned(),
+                }
+            )),
+            None => return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "Book does not have a cube closer to solved on the way down"
+            )),
+        };
+
+        let mut word = Word::new();
+        word.extend(actions);
+        Ok(word)
+    }
+
+    /// The actions taken by [`Book::solve`], or `None` if it would fail because of missing cubes
+    fn descend(&self, cube: &Cube<Position>) -> io::Result<Option<Vec<A>>> {
+        let Some(mut entry) = self.get_entry(cube)? else { return Ok(None) };
+        let mut cube = cube.clone();
+        let mut actions = vec![];
+
+        loop {
+            if let Some(solution) = entry.solution {
+                actions.extend(solution);
+                return Ok(Some(actions));
+            }
+            let depth: u64 = entry.depth.into();
+            if depth == 0 {
+                return Ok(Some(actions));
+            }
+
+            // The neighbor closest to solved, trying the best actions first so they win ties. In a
+            // book of distances that is always one step closer, but other depths only need to fall.
+            let best = entry.best;
+            let candidates = best.iter().chain(A::ALL.iter().copied().filter(|&a| !best.contains(a)));
+            let mut next: Option<(A, Cube<Position>, Entry<D, A>)> = None;
+            for a in candidates {
+                let neighbor = cube.clone().make_move(a);
+                if let Some(e) = self.get_entry(&neighbor)? {
+                    let closer = next.as_ref().map_or(depth, |(_, _, n)| n.depth.into());
+                    if e.depth.into() < closer {
+                        next = Some((a, neighbor, e));
+                    }
+                }
+            }
+            let Some((a, neighbor, e)) = next else { return Ok(None) };
+            actions.push(a);
+            cube = neighbor;
+            entry = e;
+        }
+    }
+
+    /// Shorten `word` by sliding a window of up to `max_window` actions over it and replacing any
+    /// subword whose state the book can reach in fewer actions. This repeats, longest windows
+    /// first, until nothing changes. The result always leaves the cube in the same state as `word`.
+    pub fn shorten(&self, word: &Word<A>, max_window: usize) -> io::Result<Word<A>> {
+        let mut actions = word.actions();
+        let solved = Cube::solved();
+
+        'search: loop {
+            for len in (1..=max_window.min(actions.len())).rev() {
+                for start in 0..=actions.len() - len {
+                    let window = &actions[start..start + len];
+                    let cube = window.iter().fold(solved.clone(), |cube, &a| cube.make_move(a));
+
+                    let replacement = if cube == solved {
+                        Some(vec![])
+                    } else {
+                        match self.get(&cube)? {
+                            Some(depth) if depth.into() < len as u64 => {
+                                // The way back to solved, undone, is another way to get here. A
+                                // stored solution need not be as short as the depth says.
+                                self.descend(&cube)?
+                                    .filter(|path| path.len() < len)
+                                    .map(|path| path.iter().rev().map(Action::inverse).collect())
+                            }
+                            _ => None,
+                        }
+                    };
+
+                    if let Some(replacement) = replacement {
+                        actions.splice(start..start + len, replacement);
+                        continue 'search;
+                    }
+                }
+            }
+            break;
+        }
+
+        let mut shorter = Word::new();
+        shorter.extend(actions);
+        Ok(shorter)
+    }
+}
+
+impl<D: Int, A: Packable + Action> Solver<A> for Book<D, A> {
+    fn solution(&self, cube: &Cube<Position>) -> io::Result<Option<Word<A>>> {
+        Ok(self.descend(cube)?.map(|actions| {
+            let mut word = Word::new();
+            word.extend(actions);
+            word
+        }))
+    }
+}
+
+/// Everything a book can record about a cube
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Entry<D, A: Action> {
+    pub depth: D,
+    /// The actions that start an optimal solution
+    pub best: ActionSet<A>,
+    /// A whole optimal solution
+    pub solution: Option<Vec<A>>,
+}
+
+/// A way two books disagree about a cube, see [`Book::diff`]
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Difference<D> {
+    /// Only the first book has the cube, at this depth
+    OnlyInFirst(Cube<Position>, D),
+    /// Only the second book has the cube, at this depth
+    OnlyInSecond(Cube<Position>, D),
+    /// Both books have the cube, at these depths
+    Depth(Cube<Position>, D, D),
+}
+
+const HAS_BEST: u8 = 1;
+const HAS_SOLUTION: u8 = 2;
+
+// A value as it is stored, see the comment on Book
+#[derive(Clone)]
+struct Value<D> {
+    depth: D,
+    best: Option<u64>,
+    solution: Option<Vec<u8>>,
+}
+
+impl<D: Int> Value<D> {
+    fn decode(bytes: &[u8]) -> Self {
+        let depth = D::from_bytes(&bytes[..D::BYTES]);
+        let flags = bytes.get(D::BYTES).copied().unwrap_or(0);
+        let mut rest = bytes.get(D::BYTES + 1..).unwrap_or_default();
+        let best = (flags & HAS_BEST != 0).then(|| {
+            let (mask, after) = rest.split_at(8);
+            rest = after;
+            u64::from_le_bytes(mask.try_into().unwrap())
+        });
+        let solution = (flags & HAS_SOLUTION != 0).then(|| rest.to_vec());
+        Self { depth, best, solution }
+    }
+
+    fn into_entry<A: Packable + Action>(self) -> Entry<D, A> {
+        Entry {
+            depth: self.depth,
+            best: ActionSet::from_bits(self.best.unwrap_or(0)),
+            solution: self.solution.map(|bytes| unpack(&bytes)),
+        }
+    }
+
+    fn encode(&self) -> Vec<u8> {
+        let mut bytes = self.depth.to_bytes().borrow().to_vec();
+        let flags = self.best.map_or(0, |_| HAS_BEST) | self.solution.as_ref().map_or(0, |_| HAS_SOLUTION);
+        // Depth-only values are stored the way they always have been
+        if flags != 0 {
+            bytes.push(flags);
+        }
+        if let Some(best) = self.best {
+            bytes.extend_from_slice(&best.to_le_bytes());
+        }
+        if let Some(solution) = &self.solution {
+            bytes.extend_from_slice(solution);
+        }
+        bytes
+    }
+
+    fn merge(self, new: Self, policy: &MergePolicy<D>) -> Self {
+        let depth = policy.merge(self.depth, new.depth);
+        if depth == self.depth && depth == new.depth {
+            let best = match (self.best, new.best) {
+                (Some(a), Some(b)) => Some(a | b),
+                (a, b) => a.or(b),
+            };
+            Self { depth, best, solution: self.solution.or(new.solution) }
+        } else if depth == new.depth {
+            new
+        } else if depth == self.depth {
+            self
+        } else {
+            Self { depth, best: None, solution: None }
+        }
+    }
+}
+
+pub(crate) trait Packable: Copy {
     const PACKED_BITS: usize;
     const PAD: u8 = u8::MAX >> (8 - Self::PACKED_BITS as u8);
 
@@ -203,11 +1100,11 @@ impl Packable for Move {
     const PACKED_BITS: usize = 6;
 
     fn to_byte(&self) -> u8 {
-        Move::ALL.iter().position(|x| x == self).unwrap() as u8
+        self.index() as u8
     }
 
     fn from_byte(byte: u8) -> Option<Self> {
-        Move::ALL.get(byte as usize).copied()
+        Move::from_index(byte as usize)
     }
 }
 
@@ -224,7 +1121,7 @@ impl Packable for QuarterTurn {
 /// Pack a byte slice into a smaller byte slice.
 /// `bytes` is the slice to be packed and `packed_bits` is the number of bits that each byte should be
 /// packed into (between 1 and 7).
-fn pack<T: Packable>(values: &[T]) -> Vec<u8> {
+pub(crate) fn pack<T: Packable>(values: &[T]) -> Vec<u8> {
     debug_assert!(0 < T::PACKED_BITS && T::PACKED_BITS < 8);
 
     let new_bits = values.len() * T::PACKED_BITS;
@@ -233,7 +1130,7 @@ fn pack<T: Packable>(values: &[T]) -> Vec<u8> {
 
     let mut bit = 0;
     let mut value_i = 0;
-    while bit <= size * 8 - T::PACKED_BITS {
+    while bit + T::PACKED_BITS <= size * 8 {
         let end_bit = bit + T::PACKED_BITS;
         let i = bit / 8;
         let bit_i = bit % 8;
@@ -258,11 +1155,10 @@ fn pack<T: Packable>(values: &[T]) -> Vec<u8> {
 /// `bytes` is the slice to be packed and `packed_bits` is the number of bits that each byte was
 /// packed into (between 1 and 7). Warning: if there are enough padding bits at the end to unpack
 /// another value, this function WILL unpack it;
-fn unpack<T: Packable>(bytes: &[u8]) -> Vec<T> {
+pub(crate) fn unpack<T: Packable>(bytes: &[u8]) -> Vec<T> {
     debug_assert!(0 < T::PACKED_BITS && T::PACKED_BITS < 8);
 
     let size = bytes.len() * 8 / T::PACKED_BITS;
-    println!("size: {size}");
     let mut new = vec![0; size];
 
     let mut bit = 0;
@@ -285,6 +1181,28 @@ fn unpack<T: Packable>(bytes: &[u8]) -> Vec<T> {
 }
 
 
+// Breadth-first fill of a book along canonical sequences, which never revisit a state this close
+// to solved
+#[cfg(test)]
+pub(crate) fn fill<A: Packable + Action>(book: &Book<u16, A>, depth: u16) {
+    use crate::action::Canonical;
+
+    let mut frontier = vec![(Word::<A>::new(), Canonical::START)];
+    book.insert(Word::new(), 0).unwrap();
+    for d in 1..=depth {
+        frontier = frontier.into_iter()
+            .flat_map(|(word, state)| state.successors::<A>().map(move |(a, next)| {
+                let mut word = word.clone();
+                word.extend([a]);
+                (word, next)
+            }))
+            .collect();
+        for (word, _) in frontier.iter() {
+            book.insert(word.clone(), d).unwrap();
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -344,21 +1262,408 @@ mod tests {
         assert_eq!(input, output);
     }
 
+    #[test]
+    fn test_shorten() {
+        let book: Book<u16, Turn> = Book::in_memory();
+        fill(&book, 2);
+
+        let mut word = Word::new();
+        word.extend([Turn::R, Turn::U, Turn::U3, Turn::R, Turn::F, Turn::F, Turn::F]);
+        let shorter = book.shorten(&word, 3).unwrap();
+        assert_eq!(shorter.cube(), word.cube());
+        assert_eq!(shorter.to_string(), "(R2 F')");
+
+        // A stored solution longer than its depth is not a shorter way to get there
+        let book: Book<u16, Turn> = Book::in_memory();
+        book.insert(Word::new(), 0).unwrap();
+        let word: Word<Turn> = "R U".parse().unwrap();
+        let solution = "U' R' F2 F2".parse::<Word<Turn>>().unwrap().actions();
+        book.insert_entry(word.cube(), Entry { depth: 1, best: ActionSet::new(), solution: Some(solution) }).unwrap();
+        assert_eq!(book.shorten(&word, 2).unwrap().actions(), word.actions());
+    }
+
+    #[test]
+    fn test_lookup_and_iteration() {
+        let book: Book<u16, Turn> = Book::in_memory();
+        assert!(book.is_empty());
+        fill(&book, 2);
+
+        // 1 + 18 + 243 states within two half turns
+        assert_eq!(book.len(), 262);
+        assert_eq!(book.get(&Cube::solved()).unwrap(), Some(0));
+        let r = Cube::solved().make_move(Turn::R);
+        assert_eq!(book.get(&r).unwrap(), Some(1));
+        let sexy: Word<Turn> = "R U R' U'".parse().unwrap();
+        assert!(book.contains(&r).unwrap());
+        assert!(!book.contains(sexy.cube()).unwrap());
+
+        let entries: Vec<_> = book.iter().collect::<io::Result<_>>().unwrap();
+        assert_eq!(entries.len(), 262);
+        for (cube, depth) in entries {
+            assert_eq!(book.get(&cube).unwrap(), Some(depth));
+        }
+
+        let first: Vec<_> = book.range(..=Cube::solved()).collect::<io::Result<_>>().unwrap();
+        assert_eq!(first, [(Cube::solved(), 0)]);
+        assert_eq!(book.range(Cube::solved()..).count(), 262);
+
+        // Only R turns leave the first seven cubelets, all on L, alone. Seven doesn't pack into
+        // whole bytes so this checks the filtering too.
+        let prefix = [Rotation::Neutral; 7];
+        assert_eq!(book.scan_prefix(&prefix).count(), 4);
+        assert_eq!(book.scan_prefix(&[]).count(), 262);
+    }
+
+    #[test]
+    fn test_merge_policy() {
+        let name = std::env::temp_dir().join(format!("rubiks_test_merge_policy_{}", std::process::id()));
+        let name = name.to_str().unwrap();
+        let r: Word<Turn> = "R".parse().unwrap();
+        let depths = |book: &Book<u16, Turn>| {
+            [3, 1, 2].map(|d| { book.insert(r.clone(), d).unwrap(); book.get(r.cube()).unwrap().unwrap() })
+        };
+
+        let _ = std::fs::remove_dir_all(name);
+        let book: Book<u16, Turn> = Book::create(name).unwrap();
+        assert_eq!(depths(&book), [3, 1, 1]);
+        drop(book);
+        // The policy is remembered and has to match
+        assert!(matches!(Book::<u16, Turn>::open(name).unwrap().policy(), MergePolicy::Min));
+        assert!(Book::<u16, Turn>::open_with_policy(name, MergePolicy::Max).is_err());
+        let _ = std::fs::remove_dir_all(name);
+
+        let policies: [(MergePolicy<u16>, [u16; 3]); 4] = [
+            (MergePolicy::Max, [3, 3, 3]),
+            (MergePolicy::FirstWrite, [3, 3, 3]),
+            (MergePolicy::LastWrite, [3, 1, 2]),
+            (MergePolicy::Custom(Arc::new(|current, new| current + new)), [3, 4, 6]),
+        ];
+        for (policy, expected) in policies {
+            let book: Book<u16, Turn> = Book::create_with_policy(name, policy.clone()).unwrap();
+            assert_eq!(depths(&book), expected, "{policy:?}");
+            drop(book);
+            if let MergePolicy::Custom(_) = policy {
+                assert!(Book::<u16, Turn>::open(name).is_err());
+            }
+            assert!(Book::<u16, Turn>::open_with_policy(name, policy).is_ok());
+            let _ = std::fs::remove_dir_all(name);
+        }
+    }
+
+    #[test]
+    fn test_merge_and_diff() {
+        let a: Book<u16, Turn> = Book::in_memory();
+        let b: Book<u16, Turn> = Book::in_memory();
+        let r: Word<Turn> = "R".parse().unwrap();
+        let u: Word<Turn> = "U".parse().unwrap();
+        let f: Word<Turn> = "F".parse().unwrap();
+        a.insert(Word::new(), 0).unwrap();
+        a.insert(r.clone(), 1).unwrap();
+        a.insert(u.clone(), 3).unwrap();
+        a.set_explored_depth(0).unwrap();
+        b.insert(Word::new(), 0).unwrap();
+        b.insert(u.clone(), 1).unwrap();
+        b.insert(f.clone(), 1).unwrap();
+        b.set_explored_depth(1).unwrap();
+
+        let mut diff: Vec<_> = Book::diff(&a, &b).map(Result::unwrap).collect();
+        diff.sort_by_key(|d| format!("{d:?}"));
+        assert_eq!(diff, [
+            Difference::Depth(u.cube().clone(), 3, 1),
+            Difference::OnlyInFirst(r.cube().clone(), 1),
+            Difference::OnlyInSecond(f.cube().clone(), 1),
+        ]);
+
+        assert_eq!(a.merge_from(&b).unwrap(), 3);
+        assert_eq!(a.get(u.cube()).unwrap(), Some(1));
+        assert_eq!(a.get(f.cube()).unwrap(), Some(1));
+        assert_eq!(a.depth_counts().unwrap(), BTreeMap::from([(0, 1), (1, 3)]));
+        assert_eq!(a.metadata().unwrap().explored_depth, Some(1));
+        assert_eq!(Book::diff(&a, &b).count(), 1);
+
+        // The merged book keeps its own policy
+        let c: Book<u16, Turn> = Book::create_in_with_policy(MemoryStore::new(), MergePolicy::Max).unwrap();
+        c.insert(u.clone(), 5).unwrap();
+        c.merge_from(&b).unwrap();
+        assert_eq!(c.get(u.cube()).unwrap(), Some(5));
+    }
+
+    #[test]
+    fn test_entries() {
+        let book: Book<u16, Turn> = Book::in_memory();
+        fill(&book, 2);
+
+        // Depth-only entries still read back
+        let r2f: Word<Turn> = "R2 F".parse().unwrap();
+        let entry = book.get_entry(r2f.cube()).unwrap().unwrap();
+        assert_eq!(entry, Entry { depth: 2, best: ActionSet::new(), solution: None });
+
+        let best = book.actions_toward_solved(r2f.cube()).unwrap();
+        assert_eq!(best.iter().collect::<Vec<_>>(), [Turn::F3]);
+        let solution = r2f.inverse().actions();
+        book.insert_entry(r2f.cube(), Entry { depth: 2, best, solution: Some(solution.clone()) }).unwrap();
+        assert_eq!(book.get(r2f.cube()).unwrap(), Some(2));
+        assert_eq!(book.best_actions(r2f.cube()).unwrap(), Some(best));
+        assert_eq!(book.stored_solution(r2f.cube()).unwrap().unwrap().actions(), solution);
+
+        // Ties combine the best actions, a worse depth changes nothing
+        let lr: Word<Turn> = "L R".parse().unwrap();
+        let cube = lr.cube();
+        book.insert_entry(cube, Entry { depth: 2, best: [Turn::R3].into_iter().collect(), solution: None }).unwrap();
+        book.insert_entry(cube, Entry { depth: 2, best: [Turn::L3].into_iter().collect(), solution: None }).unwrap();
+        book.insert_entry(cube, Entry { depth: 3, best: [Turn::U].into_iter().collect(), solution: None }).unwrap();
+        let best = book.best_actions(cube).unwrap().unwrap();
+        assert_eq!(best, book.actions_toward_solved(cube).unwrap());
+        assert_eq!(best.iter().collect::<Vec<_>>(), [Turn::L3, Turn::R3]);
+        assert_eq!(book.iter().count(), 262);
+
+        // An empty set of best actions is not stored, so the value stays depth-only
+        let sexy: Word<Turn> = "R U R' U'".parse().unwrap();
+        book.insert_entry(sexy.cube(), Entry { depth: 4, best: ActionSet::new(), solution: None }).unwrap();
+        let value = book.store.get(Table::Entries, &pack(&sexy.cube().cubelets)).unwrap().unwrap();
+        assert_eq!(value, 4u16.to_le_bytes());
+    }
+
+    #[test]
+    fn test_solve() {
+        let book: Book<u16, Turn> = Book::in_memory();
+        fill(&book, 2);
+
+        let word: Word<Turn> = "R2 F".parse().unwrap();
+        let solution = book.solve(word.cube()).unwrap();
+        assert_eq!(solution.len(), 2);
+        assert_eq!(*word.concat(&solution).cube(), Cube::solved());
+        assert!(book.solve(&Cube::solved()).unwrap().is_empty());
+
+        // Out of reach
+        let sexy: Word<Turn> = "R U R' U'".parse().unwrap();
+        assert_eq!(book.solve(sexy.cube()).unwrap_err().kind(), io::ErrorKind::NotFound);
+
+        // In the book, but nothing at depth 3 leads down from it
+        book.insert(sexy.clone(), 4).unwrap();
+        assert_eq!(book.solve(sexy.cube()).unwrap_err().kind(), io::ErrorKind::InvalidData);
+
+        // Stored solutions are used as they are
+        let solution = sexy.inverse().actions();
+        book.insert_entry(sexy.cube(), Entry { depth: 4, best: ActionSet::new(), solution: Some(solution.clone()) }).unwrap();
+        assert_eq!(book.solve(sexy.cube()).unwrap().actions(), solution);
+
+        // Depths that aren't distances only need to fall, and the lowest neighbor is taken
+        let book: Book<u16, Turn> = Book::in_memory();
+        book.insert(Word::new(), 0).unwrap();
+        book.insert("R".parse().unwrap(), 3).unwrap();
+        book.insert("R U2".parse().unwrap(), 6).unwrap();
+        let word: Word<Turn> = "R U".parse().unwrap();
+        book.insert(word.clone(), 9).unwrap();
+        assert_eq!(book.solve(word.cube()).unwrap().actions(), [Turn::U3, Turn::R3]);
+    }
+
+    #[test]
+    fn test_metadata() {
+        let name = std::env::temp_dir().join(format!("rubiks_test_metadata_{}", std::process::id()));
+        let name = name.to_str().unwrap();
+        let _ = std::fs::remove_dir_all(name);
+        let book: Book<u16, Turn> = Book::create(name).unwrap();
+        fill(&book, 2);
+        book.set_explored_depth(2).unwrap();
+        book.set_parameters("breadth-first, canonical sequences").unwrap();
+
+        // Moving a cube to a smaller depth moves it between counts
+        let sexy: Word<Turn> = "R U R' U'".parse().unwrap();
+        book.insert(sexy.clone(), 5).unwrap();
+        book.insert(sexy.clone(), 4).unwrap();
+
+        let meta = book.metadata().unwrap();
+        assert_eq!(meta.version, FORMAT_VERSION);
+        assert_eq!((meta.depth_type.as_str(), meta.action_type.as_str()), ("u16", "turn"));
+        assert_eq!(meta.merge_policy, "min");
+        assert_eq!(meta.generators, "L L2 L' R R2 R' F F2 F' B B2 B' D D2 D' U U2 U'");
+        assert_eq!(meta.explored_depth, Some(2));
+        assert_eq!(meta.parameters.as_deref(), Some("breadth-first, canonical sequences"));
+        assert_eq!(meta.depth_counts, BTreeMap::from([(0, 1), (1, 18), (2, 243), (4, 1)]));
+        drop(book);
+
+        assert!(Book::<u8, Turn>::open(name).is_err());
+        assert!(Book::<u16, QuarterTurn>::open(name).is_err());
+        assert_eq!(Book::<u16, Turn>::open(name).unwrap().metadata().unwrap(), meta);
+        let _ = std::fs::remove_dir_all(name);
+    }
+
+    #[test]
+    fn test_upgrade_from_type_names() {
+        let name = std::env::temp_dir().join(format!("rubiks_test_upgrade_from_type_names_{}", std::process::id()));
+        let name = name.to_str().unwrap();
+        let _ = std::fs::remove_dir_all(name);
+
+        // A book as it was written before the metadata tree
+        let cubes = [Cube::solved(), Cube::solved().make_move(Turn::R), Cube::solved().make_move(Turn::U2)];
+        {
+            let db = sled::open(name).unwrap();
+            let inner = db.open_tree(b"book").unwrap();
+            inner.insert(LEGACY_DEPTH_ENTRY, "u16").unwrap();
+            inner.insert(LEGACY_ACTION_ENTRY, "some_old_crate_name::action::Turn").unwrap();
+            for (depth, cube) in [0u16, 1, 1].iter().zip(cubes.iter()) {
+                inner.insert(pack(&cube.cubelets), &depth.to_le_bytes()).unwrap();
+            }
+            db.flush().unwrap();
+        }
+
+        let book: Book<u16, Turn> = Book::open(name).unwrap();
+        let meta = book.metadata().unwrap();
+        assert_eq!(meta.version, FORMAT_VERSION);
+        assert_eq!(meta.action_type, "turn");
+        assert_eq!(meta.merge_policy, "min");
+        assert_eq!(meta.depth_counts, BTreeMap::from([(0, 1), (1, 2)]));
+        assert_eq!(book.len(), 3);
+        assert_eq!(book.get(&cubes[1]).unwrap(), Some(1));
+        drop(book);
+
+        // Books from a newer version are refused rather than misread
+        {
+            let db = sled::open(name).unwrap();
+            db.open_tree(b"meta").unwrap().insert(VERSION_KEY, &(FORMAT_VERSION as u64 + 1).to_le_bytes()).unwrap();
+            db.flush().unwrap();
+        }
+        assert_eq!(Book::<u16, Turn>::open(name).err().map(|e| e.kind()), Some(io::ErrorKind::InvalidData));
+
+        assert_eq!(tag_from_type_name("rubiks::action::QuarterTurn"), "quarter_turn");
+        let _ = std::fs::remove_dir_all(name);
+    }
+
+    #[test]
+    fn test_upgrade_wrong_depth_type() {
+        let name = std::env::temp_dir().join(format!("rubiks_test_upgrade_wrong_depth_type_{}", std::process::id()));
+        let name = name.to_str().unwrap();
+        let cubes = [Cube::solved(), Cube::solved().make_move(Turn::R)];
+        let legacy_keys = [LEGACY_DEPTH_ENTRY, LEGACY_ACTION_ENTRY];
+        let write_legacy = |depth_type: &str, depths: [&[u8]; 2]| {
+            let _ = std::fs::remove_dir_all(name);
+            let db = sled::open(name).unwrap();
+            let inner = db.open_tree(b"book").unwrap();
+            inner.insert(LEGACY_DEPTH_ENTRY, depth_type).unwrap();
+            inner.insert(LEGACY_ACTION_ENTRY, "rubiks::action::Turn").unwrap();
+            for (depth, cube) in depths.iter().zip(cubes.iter()) {
+                inner.insert(pack(&cube.cubelets), *depth).unwrap();
+            }
+            db.flush().unwrap();
+        };
+        let legacy_keys_left = || {
+            let db = sled::open(name).unwrap();
+            let inner = db.open_tree(b"book").unwrap();
+            let left = legacy_keys.iter().all(|key| inner.contains_key(key).unwrap());
+            let no_meta = db.open_tree(b"meta").unwrap().is_empty();
+            left && no_meta
+        };
+
+        // A u8 book opened as u16 is refused before anything is written
+        write_legacy("u8", [&0u8.to_le_bytes(), &1u8.to_le_bytes()]);
+        let err = Book::<u16, Turn>::open(name).err().unwrap();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        assert!(legacy_keys_left());
+        let book: Book<u8, Turn> = Book::open(name).unwrap();
+        assert_eq!(book.metadata().unwrap().depth_counts, BTreeMap::from([(0, 1), (1, 1)]));
+        drop(book);
+
+        // And the other way around
+        write_legacy("u16", [&0u16.to_le_bytes(), &1u16.to_le_bytes()]);
+        let err = Book::<u8, Turn>::open(name).err().unwrap();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        assert!(legacy_keys_left());
+        assert_eq!(Book::<u16, QuarterTurn>::open(name).err().map(|e| e.kind()), Some(io::ErrorKind::InvalidData));
+        assert!(legacy_keys_left());
+        let book: Book<u16, Turn> = Book::open(name).unwrap();
+        assert_eq!(book.get(&cubes[1]).unwrap(), Some(1));
+        drop(book);
+        let _ = std::fs::remove_dir_all(name);
+    }
+
+    #[test]
+    fn test_shortest_equivalent() {
+        let book: Book<u16, Turn> = Book::in_memory();
+        fill(&book, 2);
+
+        let word: Word<Turn> = "R U F F' U' R".parse().unwrap();
+        let shortest = word.shortest_equivalent(&book).unwrap();
+        assert!(shortest.equivalent(&word));
+        assert_eq!(shortest.to_string(), "(R2)");
+
+        // Nothing shorter is known, so the word comes back as it is
+        let word: Word<Turn> = "R U F".parse().unwrap();
+        assert_eq!(word.shortest_equivalent(&book).unwrap().to_string(), "(R U F)");
+    }
+
+    #[test]
+    fn test_stats() {
+        let book: Book<u16, Turn> = Book::in_memory();
+        fill(&book, 2);
+        let stats = book.stats().unwrap();
+        assert_eq!(stats.depth_counts, BTreeMap::from([(0, 1), (1, 18), (2, 243)]));
+        assert_eq!((stats.total, stats.max_depth(), stats.size_on_disk), (262, Some(2), 0));
+        assert!((stats.mean_depth().unwrap() - (18.0 + 2.0 * 243.0) / 262.0).abs() < 1e-9);
+        assert_eq!(stats.mean_branching(), None);
+        assert!(stats.to_string().contains("    2           243  ########################################"));
+
+        // Branching follows the merged best actions
+        let cube = "R U".parse::<Word<Turn>>().unwrap().cube().clone();
+        let entry = |depth, best: &[Turn]| Entry { depth, best: best.iter().copied().collect(), solution: None };
+        book.insert_entry(&cube, entry(2, &[Turn::U3])).unwrap();
+        book.insert_entry(&cube, entry(2, &[Turn::R3])).unwrap();
+        book.insert_entry(&Cube::solved(), entry(0, &[])).unwrap();
+        assert_eq!(book.stats().unwrap().branching_counts, BTreeMap::from([(2, 1)]));
+        book.insert_entry(&cube, entry(1, &[Turn::L])).unwrap();
+        let stats = book.stats().unwrap();
+        assert_eq!(stats.branching_counts, BTreeMap::from([(1, 1)]));
+        assert_eq!(stats.depth_counts, BTreeMap::from([(0, 1), (1, 19), (2, 242)]));
+        assert_eq!(stats.mean_branching(), Some(1.0));
+    }
+
+    #[test]
+    fn test_stores() {
+        use crate::store::FileStore;
+
+        let name = std::env::temp_dir().join(format!("rubiks_test_stores_{}.bin", std::process::id()));
+        let name = name.to_str().unwrap();
+        let _ = std::fs::remove_file(name);
+        let book: Book<u16, Turn> = Book::create_in(FileStore::create(name).unwrap()).unwrap();
+        fill(&book, 2);
+        book.set_explored_depth(2).unwrap();
+        let meta = book.metadata().unwrap();
+        let entries: Vec<_> = book.entries().map(Result::unwrap).collect();
+        book.flush().unwrap();
+        assert!(book.size().unwrap() > 0);
+        drop(book);
+
+        assert_eq!(Book::<u16, Turn>::create_in(FileStore::open(name).unwrap()).err().map(|e| e.kind()), Some(io::ErrorKind::AlreadyExists));
+        assert!(Book::<u8, Turn>::open_in(FileStore::open(name).unwrap()).is_err());
+        let book: Book<u16, Turn> = Book::open_in(FileStore::open(name).unwrap()).unwrap();
+        assert_eq!(book.metadata().unwrap(), meta);
+        assert_eq!(book.entries().map(Result::unwrap).collect::<Vec<_>>(), entries);
+        drop(book);
+        let _ = std::fs::remove_file(name);
+
+        assert_eq!(Book::<u16, Turn>::open_in(MemoryStore::new()).err().map(|e| e.kind()), Some(io::ErrorKind::NotFound));
+        let memory: Book<u16, Turn> = Book::in_memory();
+        fill(&memory, 2);
+        assert_eq!(memory.entries().map(Result::unwrap).collect::<Vec<_>>(), entries);
+        assert_eq!(memory.size().unwrap(), 0);
+    }
+
     #[test]
     fn test_create_and_open_book() {
-        const NAME: &str = "test_create_book";
-        let _ = std::fs::remove_dir_all(NAME);
+        let name = std::env::temp_dir().join(format!("rubiks_test_create_book_{}", std::process::id()));
+        let name = name.to_str().unwrap();
+        let _ = std::fs::remove_dir_all(name);
 
-        let res1: Result<Book<u16, Move>, _> = Book::open(NAME);
+        let res1: Result<Book<u16, Move>, _> = Book::open(name);
         assert!(res1.is_err());
-        assert!(!std::path::Path::new(NAME).exists());
+        assert!(!std::path::Path::new(name).exists());
 
-        let new_book: Book<u16, Move> = Book::create(NAME).unwrap();
-        let res2: Result<Book<u16, Move>, _> = Book::create(NAME);
+        let new_book: Book<u16, Move> = Book::create(name).unwrap();
+        let res2: Result<Book<u16, Move>, _> = Book::create(name);
         assert!(res2.is_err());
 
         drop(new_book);
-        let _ = std::fs::remove_dir_all(NAME);
-        assert!(!std::path::Path::new(NAME).exists());
+        let _ = std::fs::remove_dir_all(name);
+        assert!(!std::path::Path::new(name).exists());
     }
 }
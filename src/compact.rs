@@ -0,0 +1,463 @@
+//! A read-only form of a finished [`Book`]: one file of fixed-width rows that is memory-mapped, so
+//! any number of processes can share it and look cubes up without copying it into memory.
+//!
+//! The file holds a header, the book's metadata, a bucket table and then the rows. Each row is the
+//! packed cube, the depth and the best actions as a little endian u64. Rows are grouped by the top
+//! bits of a hash of their cube, with about one row per bucket, so finding a cube only compares it
+//! against the few rows in its bucket. After the rows comes an index of the rows at each depth, so
+//! cubes of one depth can be picked at random without looking at the others.
+
+#![allow(private_bounds)]
+use std::{
+    borrow::Borrow,
+    collections::BTreeMap,
+    fs::{self, File},
+    io,
+    marker::PhantomData,
+    path::Path,
+    sync::Arc,
+};
+
+use memmap2::{Mmap, MmapMut};
+
+use crate::{
+    action::{Action, ActionSet, Turn},
+    book::{self, Book, Entry, Int, Metadata, Packable, KEY_LEN},
+    cube::{Cube, Position},
+    cubelet::Rotation,
+    store::{BookStore, MemoryStore, Table},
+};
+
+const MAGIC: &[u8; 8] = b"RBKCMPCT";
+/// The version of the compact file layout, separate from [`book::FORMAT_VERSION`]
+pub const COMPACT_VERSION: u32 = 2;
+// The magic string, the version and the depth width as u32s, the number of rows as a u64, then the
+// number of bucket bits, the length of the metadata and the number of depths as u32s
+const HEADER_LEN: usize = 8 + 4 + 4 + 8 + 4 + 4 + 4;
+
+/// A book written by [`Book::compact`]. Cloning it shares the mapping.
+#[derive(Clone)]
+pub struct CompactBook<Depth = u16, Action = Turn> {
+    map: Arc<Mmap>,
+    metadata: Metadata,
+    rows: usize,
+    bucket_bits: u32,
+    buckets_offset: usize,
+    rows_offset: usize,
+    // Each depth with the part of the row list that holds its rows
+    depths: BTreeMap<u64, (usize, usize)>,
+    row_list_offset: usize,
+    _phantom: PhantomData<(Depth, Action)>,
+}
+
+impl<D: Int, A: Packable + Action> Book<D, A> {
+    /// Write the book to `path` as a [`CompactBook`], replacing anything already there. Stored
+    /// solutions are left out so every row has the same width. The book is read twice, once to size
+    /// the buckets and once to fill them, so it mustn't change in between.
+    ///
+    /// The file is written next to `path` and renamed over it once it is complete, so a compact book
+    /// already mapped from `path` keeps its old contents.
+    pub fn compact(&self, path: impl AsRef<Path>) -> io::Result<()> {
+        let path = path.as_ref();
+        let name = path.file_name().ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "Path has no file name"))?;
+        let temp = path.with_file_name(format!(".{}.{}.tmp", name.to_string_lossy(), std::process::id()));
+        let written = self.write_compact(&temp).and_then(|()| fs::rename(&temp, path));
+        if written.is_err() {
+            let _ = fs::remove_file(&temp);
+        }
+        written
+    }
+
+    fn write_compact(&self, path: &Path) -> io::Result<()> {
+        let mut meta = vec![];
+        for pair in self.store.iter(Table::Meta) {
+            let (key, value) = pair?;
+            for bytes in [key, value] {
+                meta.extend_from_slice(&(bytes.len() as u32).to_le_bytes());
+                meta.extend_from_slice(&bytes);
+            }
+        }
+
+        // The first bucket of each row, then where each bucket starts, and the same for depths
+        let bucket_bits = bucket_bits(self.len());
+        let mut starts = vec![0u64; (1 << bucket_bits) + 1];
+        let mut depth_starts = BTreeMap::<u64, u64>::new();
+        for entry in self.iter() {
+            let (cube, depth) = entry?;
+            starts[bucket(&book::pack(&cube.cubelets), bucket_bits) + 1] += 1;
+            *depth_starts.entry(depth.into()).or_default() += 1;
+        }
+        for i in 1..starts.len() {
+            starts[i] += starts[i - 1];
+        }
+        let rows = *starts.last().unwrap() as usize;
+        let mut start = 0;
+        for count in depth_starts.values_mut() {
+            start += *count;
+            *count = start - *count;
+        }
+
+        let row_len = row_len::<D>();
+        let buckets_offset = (HEADER_LEN + meta.len()).next_multiple_of(8);
+        let rows_offset = buckets_offset + 8 * starts.len();
+        let depth_index_offset = (rows_offset + rows * row_len).next_multiple_of(8);
+        let row_list_offset = depth_index_offset + 16 * depth_starts.len();
+        let file = File::options().read(true).write(true).create(true).truncate(true).open(path)?;
+        file.set_len((row_list_offset + 8 * rows) as u64)?;
+        // Safety: the file was just made by this process under a name only it uses, and nothing maps
+        // it until it is renamed into place
+        let mut map = unsafe { MmapMut::map_mut(&file)? };
+
+        map[..8].copy_from_slice(MAGIC);
+        map[8..12].copy_from_slice(&COMPACT_VERSION.to_le_bytes());
+        map[12..16].copy_from_slice(&(D::BYTES as u32).to_le_bytes());
+        map[16..24].copy_from_slice(&(rows as u64).to_le_bytes());
+        map[24..28].copy_from_slice(&bucket_bits.to_le_bytes());
+        map[28..32].copy_from_slice(&(meta.len() as u32).to_le_bytes());
+        map[32..36].copy_from_slice(&(depth_starts.len() as u32).to_le_bytes());
+        map[HEADER_LEN..HEADER_LEN + meta.len()].copy_from_slice(&meta);
+        for (i, start) in starts.iter().enumerate() {
+            let at = buckets_offset + 8 * i;
+            map[at..at + 8].copy_from_slice(&start.to_le_bytes());
+        }
+        // Each depth and where its rows start in the list of rows by depth
+        for (i, (depth, start)) in depth_starts.iter().enumerate() {
+            let at = depth_index_offset + 16 * i;
+            map[at..at + 8].copy_from_slice(&depth.to_le_bytes());
+            map[at + 8..at + 16].copy_from_slice(&start.to_le_bytes());
+        }
+
+        // Each row goes in the next free place in its bucket
+        let changed = || io::Error::new(io::ErrorKind::InvalidData, "Book changed while it was being compacted");
+        let mut next = starts.clone();
+        for entry in self.entries() {
+            let (cube, entry) = entry?;
+            let key = book::pack(&cube.cubelets);
+            let bucket = bucket(&key, bucket_bits);
+            if next[bucket] == starts[bucket + 1] {
+                return Err(changed());
+            }
+            let row = next[bucket];
+            let at = rows_offset + row as usize * row_len;
+            next[bucket] += 1;
+
+            let Some(next_at_depth) = depth_starts.get_mut(&entry.depth.into()) else { return Err(changed()) };
+            let list_at = row_list_offset + 8 * *next_at_depth as usize;
+            map[list_at..list_at + 8].copy_from_slice(&row.to_le_bytes());
+            *next_at_depth += 1;
+
+            let row = &mut map[at..at + row_len];
+            row[..KEY_LEN].copy_from_slice(&key);
+            row[KEY_LEN..KEY_LEN + D::BYTES].copy_from_slice(entry.depth.to_bytes().borrow());
+            row[KEY_LEN + D::BYTES..].copy_from_slice(&entry.best.bits().to_le_bytes());
+        }
+        if next[..next.len() - 1] != starts[1..] {
+            return Err(changed());
+        }
+        map.flush()?;
+        file.sync_all()
+    }
+}
+
+impl<D: Int, A: Packable + Action> CompactBook<D, A> {
+    /// Map the compact book at `path`, checking that it was made for `D` and `A`. A truncated file or
+    /// a corrupt header or depth index fails with [`io::ErrorKind::InvalidData`]. Only those are read
+    /// here, so opening stays cheap however many processes map the same book.
+    pub fn open(path: impl AsRef<Path>) -> io::Result<Self> {
+        let path = path.as_ref();
+        let file = File::open(path)?;
+        // Safety: compact books are never written again once made. `Book::compact` replaces a file by
+        // renaming a new one over it, which leaves this mapping of the old one as it was.
+        let map = unsafe { Mmap::map(&file)? };
+        let invalid = |what: &str| io::Error::new(io::ErrorKind::InvalidData, format!("{}: {what}", path.display()));
+
+        if map.len() < HEADER_LEN || &map[..8] != MAGIC {
+            return Err(invalid("not a compact book"));
+        }
+        let u32_at = |at: usize| u32::from_le_bytes(map[at..at + 4].try_into().unwrap());
+        let version = u32_at(8);
+        if version != COMPACT_VERSION {
+            return Err(invalid(&format!("compact version {version} is not {COMPACT_VERSION}")));
+        }
+        if u32_at(12) as usize != D::BYTES {
+            return Err(invalid(&format!("depths are {} bytes wide, not {}", u32_at(12), D::BYTES)));
+        }
+        let rows = u64::from_le_bytes(map[16..24].try_into().unwrap()) as usize;
+        let bucket_bits = u32_at(24);
+        let meta_len = u32_at(28) as usize;
+        let n_depths = u32_at(32) as usize;
+        if bucket_bits > 40 {
+            return Err(invalid("too many buckets"));
+        }
+        // Every offset is checked for overflow, since the sizes come from the file
+        let offsets = || -> Option<(usize, usize, usize, usize)> {
+            let buckets_offset = HEADER_LEN.checked_add(meta_len)?.checked_next_multiple_of(8)?;
+            let rows_offset = buckets_offset.checked_add(8 * ((1 << bucket_bits) + 1))?;
+            let depth_index_offset = rows.checked_mul(row_len::<D>())?
+                .checked_add(rows_offset)?
+                .checked_next_multiple_of(8)?;
+            let row_list_offset = n_depths.checked_mul(16)?.checked_add(depth_index_offset)?;
+            let end = rows.checked_mul(8)?.checked_add(row_list_offset)?;
+            (map.len() == end).then_some((buckets_offset, rows_offset, depth_index_offset, row_list_offset))
+        };
+        let (buckets_offset, rows_offset, depth_index_offset, row_list_offset) =
+            offsets().ok_or_else(|| invalid("file is the wrong length"))?;
+
+        // The depth index is small enough to check here. The bucket table and the row list are as
+        // long as the rows, so their values are only checked when they are used.
+        let u64_at = |at: usize| u64::from_le_bytes(map[at..at + 8].try_into().unwrap());
+        let mut depths = BTreeMap::new();
+        for i in 0..n_depths {
+            let at = depth_index_offset + 16 * i;
+            let end = if i + 1 < n_depths { u64_at(at + 24) } else { rows as u64 };
+            let (depth, start) = (u64_at(at), u64_at(at + 8));
+            let ascending = depths.last_key_value().map_or(start == 0, |(&last, &(_, last_end))| {
+                last < depth && last_end == start as usize
+            });
+            if !ascending || start > end || end > rows as u64 {
+                return Err(invalid("depth index is corrupt"));
+            }
+            depths.insert(depth, (start as usize, end as usize));
+        }
+
+        // The metadata is read back into a store so it is checked the same way a book's is
+        let meta = MemoryStore::new();
+        let mut bytes = map.get(HEADER_LEN..HEADER_LEN + meta_len).ok_or_else(|| invalid("metadata is cut short"))?;
+        while !bytes.is_empty() {
+            let mut next = || -> Option<&[u8]> {
+                let len = u32::from_le_bytes(bytes.get(..4)?.try_into().unwrap()) as usize;
+                let field = bytes.get(4..4 + len)?;
+                bytes = &bytes[4 + len..];
+                Some(field)
+            };
+            let (key, value) = next().zip(next()).ok_or_else(|| invalid("metadata is cut short"))?;
+            meta.insert(Table::Meta, key, value)?;
+        }
+        book::check_format::<D, A>(&meta)?;
+        let metadata = book::read_metadata(&meta)?;
+
+        Ok(Self {
+            map: Arc::new(map),
+            metadata,
+            rows,
+            bucket_bits,
+            buckets_offset,
+            rows_offset,
+            depths,
+            row_list_offset,
+            _phantom: PhantomData,
+        })
+    }
+
+    /// The metadata of the book this was made from
+    pub fn metadata(&self) -> &Metadata {
+        &self.metadata
+    }
+
+    /// The number of cubes, which are numbered from 0 for [`CompactBook::row`]
+    pub fn len(&self) -> usize {
+        self.rows
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.rows == 0
+    }
+
+    /// The row of `cube`, if the book has it. A corrupt bucket table can make a cube seem to be
+    /// missing, but never gives the row of another cube.
+    pub fn find(&self, cube: &Cube<Position>) -> Option<usize> {
+        let key = book::pack(&cube.cubelets);
+        let bucket = bucket(&key, self.bucket_bits);
+        let start = |i: usize| {
+            let at = self.buckets_offset + 8 * i;
+            (u64::from_le_bytes(self.map[at..at + 8].try_into().unwrap()) as usize).min(self.rows)
+        };
+        (start(bucket)..start(bucket + 1)).find(|&row| self.row_bytes(row)[..KEY_LEN] == key[..])
+    }
+
+    pub fn contains(&self, cube: &Cube<Position>) -> bool {
+        self.find(cube).is_some()
+    }
+
+    /// The depth recorded for `cube`, if the book has it
+    pub fn get(&self, cube: &Cube<Position>) -> Option<D> {
+        self.find(cube).map(|row| self.depth(row))
+    }
+
+    /// Everything recorded about `cube`, which never includes a solution
+    pub fn get_entry(&self, cube: &Cube<Position>) -> Option<Entry<D, A>> {
+        self.find(cube).map(|row| self.entry(row))
+    }
+
+    /// The cube in row `index` with everything recorded about it
+    pub fn row(&self, index: usize) -> Option<(Cube<Position>, Entry<D, A>)> {
+        (index < self.rows).then(|| (self.cube(index), self.entry(index)))
+    }
+
+    /// The depth of the cube in row `index`, without decoding the cube
+    pub fn depth_at(&self, index: usize) -> Option<D> {
+        (index < self.rows).then(|| self.depth(index))
+    }
+
+    /// The rows of the cubes at `depth`. The order is fixed but otherwise means nothing. A row past
+    /// the end of the book, which only a corrupt file has, is an [`io::ErrorKind::InvalidData`].
+    pub fn rows_at_depth(&self, depth: u64) -> impl ExactSizeIterator<Item = io::Result<usize>> + '_ {
+        let (start, end) = self.depths.get(&depth).copied().unwrap_or((0, 0));
+        (start..end).map(|i| self.listed_row(i))
+    }
+
+    /// The `index`th row at `depth`, in the order of [`CompactBook::rows_at_depth`]
+    pub fn row_at_depth(&self, depth: u64, index: usize) -> io::Result<Option<usize>> {
+        let Some(&(start, end)) = self.depths.get(&depth) else { return Ok(None) };
+        (index < end - start).then(|| self.listed_row(start + index)).transpose()
+    }
+
+    fn listed_row(&self, i: usize) -> io::Result<usize> {
+        let at = self.row_list_offset + 8 * i;
+        let row = u64::from_le_bytes(self.map[at..at + 8].try_into().unwrap()) as usize;
+        if row >= self.rows {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "Compact book's list of rows by depth is corrupt"));
+        }
+        Ok(row)
+    }
+
+    /// Every cube in row order
+    pub fn iter(&self) -> impl Iterator<Item = (Cube<Position>, Entry<D, A>)> + '_ {
+        (0..self.rows).map(|row| (self.cube(row), self.entry(row)))
+    }
+
+    fn row_bytes(&self, row: usize) -> &[u8] {
+        let at = self.rows_offset + row * row_len::<D>();
+        &self.map[at..at + row_len::<D>()]
+    }
+
+    fn cube(&self, row: usize) -> Cube<Position> {
+        let cubelets: Vec<Rotation> = book::unpack(&self.row_bytes(row)[..KEY_LEN]);
+        Cube::new(cubelets[..20].try_into().unwrap())
+    }
+
+    fn depth(&self, row: usize) -> D {
+        D::from_bytes(&self.row_bytes(row)[KEY_LEN..KEY_LEN + D::BYTES])
+    }
+
+    fn entry(&self, row: usize) -> Entry<D, A> {
+        let best = u64::from_le_bytes(self.row_bytes(row)[KEY_LEN + D::BYTES..].try_into().unwrap());
+        Entry { depth: self.depth(row), best: ActionSet::from_bits(best), solution: None }
+    }
+}
+
+fn row_len<D: Int>() -> usize {
+    KEY_LEN + D::BYTES + 8
+}
+
+/// Enough bits for about one row per bucket
+fn bucket_bits(rows: usize) -> u32 {
+    rows.max(1).next_power_of_two().trailing_zeros()
+}
+
+fn bucket(key: &[u8], bits: u32) -> usize {
+    if bits == 0 { 0 } else { (hash(key) >> (64 - bits)) as usize }
+}
+
+/// FNV-1a followed by the MurmurHash3 finalizer, so the top bits are well mixed. This is part of
+/// the file format and must not change without bumping [`COMPACT_VERSION`].
+fn hash(key: &[u8]) -> u64 {
+    let mut h = key.iter().fold(0xcbf29ce484222325u64, |h, &b| (h ^ b as u64).wrapping_mul(0x100000001b3));
+    h ^= h >> 33;
+    h = h.wrapping_mul(0xff51afd7ed558ccd);
+    h ^= h >> 33;
+    h = h.wrapping_mul(0xc4ceb9fe1a85ec53);
+    h ^ (h >> 33)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::word::Word;
+
+    #[test]
+    fn test_compact() {
+        let name = std::env::temp_dir().join(format!("rubiks_test_compact_{}.bin", std::process::id()));
+        let name = name.to_str().unwrap();
+        let book: Book<u16, Turn> = Book::in_memory();
+        book.insert(Word::new(), 0).unwrap();
+        for &t in Turn::ALL {
+            let mut word = Word::new();
+            word.extend([t]);
+            book.insert(word, 1).unwrap();
+        }
+        for (i, &t) in Turn::ALL.iter().enumerate() {
+            let mut word = Word::new();
+            word.extend([Turn::ALL[(i + 3) % Turn::ALL.len()], t]);
+            if !book.contains(word.cube()).unwrap() {
+                book.insert_entry(word.cube(), Entry { depth: 2, best: ActionSet::from_iter([t]), solution: None }).unwrap();
+            }
+        }
+        book.set_explored_depth(1).unwrap();
+        book.compact(name).unwrap();
+
+        let compact: CompactBook<u16, Turn> = CompactBook::open(name).unwrap();
+        assert_eq!(compact.len(), book.len());
+        assert_eq!(compact.metadata(), &book.metadata().unwrap());
+        for entry in book.entries() {
+            let (cube, entry) = entry.unwrap();
+            assert_eq!(compact.get_entry(&cube), Some(entry));
+        }
+        let mut rows: Vec<_> = compact.iter().map(|(cube, _)| cube).collect();
+        rows.sort_by_key(|cube| book::pack(&cube.cubelets));
+        assert_eq!(rows, book.iter().map(|entry| entry.unwrap().0).collect::<Vec<_>>());
+        assert_eq!(compact.row(3).map(|(_, entry)| entry.depth), compact.depth_at(3));
+        assert_eq!(compact.row(compact.len()), None);
+        let far = "R U F L".parse::<Word<Turn>>().unwrap();
+        assert_eq!(compact.get(far.cube()), None);
+
+        for (depth, count) in book.depth_counts().unwrap() {
+            let rows: Vec<_> = compact.rows_at_depth(depth).map(Result::unwrap).collect();
+            assert_eq!(rows.len() as u64, count);
+            assert!(rows.iter().all(|&row| compact.depth_at(row).map(Into::into) == Some(depth)));
+            assert_eq!(compact.row_at_depth(depth, 0).unwrap(), Some(rows[0]));
+        }
+        assert_eq!(compact.rows_at_depth(7).len(), 0);
+
+        assert!(CompactBook::<u8, Turn>::open(name).is_err());
+        assert!(CompactBook::<u16, crate::action::QuarterTurn>::open(name).is_err());
+
+        // Compacting over a mapped book leaves the mapping as it was
+        let bytes = std::fs::read(name).unwrap();
+        let empty = Book::<u16, Turn>::in_memory();
+        empty.compact(name).unwrap();
+        assert_eq!(compact.len(), book.len());
+        assert_eq!(compact.get_entry(far.cube()), None);
+        assert!(compact.contains(&Cube::solved()));
+
+        // An empty book still makes a valid file
+        assert!(CompactBook::<u16, Turn>::open(name).unwrap().is_empty());
+
+        // A damaged file fails to open or to read instead of panicking
+        let damaged = |bytes: &[u8]| {
+            std::fs::write(name, bytes).unwrap();
+            CompactBook::<u16, Turn>::open(name)
+        };
+        let kind = |result: io::Result<CompactBook<u16, Turn>>| result.err().map(|e| e.kind());
+        assert_eq!(kind(damaged(&bytes[..bytes.len() - 1])), Some(io::ErrorKind::InvalidData));
+        assert_eq!(kind(damaged(&bytes[..HEADER_LEN])), Some(io::ErrorKind::InvalidData));
+        let mut corrupt = bytes.clone();
+        corrupt[16..24].copy_from_slice(&u64::MAX.to_le_bytes());
+        assert_eq!(kind(damaged(&corrupt)), Some(io::ErrorKind::InvalidData));
+
+        let mut corrupt = bytes.clone();
+        corrupt[compact.buckets_offset..compact.rows_offset].fill(0xff);
+        let opened = damaged(&corrupt).unwrap();
+        assert!(book.iter().all(|entry| opened.find(&entry.unwrap().0).is_none()));
+
+        let mut corrupt = bytes.clone();
+        let last = corrupt.len() - 8;
+        corrupt[last..].copy_from_slice(&u64::MAX.to_le_bytes());
+        let opened = damaged(&corrupt).unwrap();
+        let deepest = *opened.depths.keys().last().unwrap();
+        assert!(opened.rows_at_depth(deepest).any(|row| row.is_err()));
+        assert!(opened.row_at_depth(deepest, opened.rows_at_depth(deepest).len() - 1).is_err());
+        assert!(damaged(&bytes).is_ok());
+        let _ = std::fs::remove_file(name);
+    }
+}
@@ -3,12 +3,14 @@
 use std::{
     array, 
     fmt::Display, 
+    io,
     marker::PhantomData,
+    str::FromStr,
 };
 
 use crate::{
     action::Move,
-    cubelet::{Axis, Rotation}
+    cubelet::{Axis, Face, Rotation}
 };
 
 /// A Rubiks' cube's state, represented by the orientation of the cubelets.
@@ -39,6 +41,20 @@ impl<T: SortBy> Display for Cube<T> {
     }
 }
 
+/// Parses the rotation string written by `Display`
+impl<T: SortBy> FromStr for Cube<T> {
+    type Err = io::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || io::Error::new(io::ErrorKind::InvalidData, format!("Invalid rotation string: {s}"));
+        let cubelets: Vec<Rotation> = s.bytes()
+            .map(|b| b.checked_sub(b'A').and_then(|i| Rotation::from_index(i as usize)))
+            .collect::<Option<_>>()
+            .ok_or_else(invalid)?;
+        Ok(Cube::new(cubelets.try_into().map_err(|_| invalid())?))
+    }
+}
+
 impl<T: SortBy> IntoIterator for Cube<T> {
     type IntoIter = array::IntoIter<Rotation, 20>;
     type Item = Rotation;
@@ -241,11 +257,91 @@ pub fn shift_backward(cubelets: &[Rotation; 20], mutations: &[Rotation; 20]) ->
         .unwrap()
 }
 
+/// Coordinates of a sticker on a face, by row and column as seen when looking straight at the face
+/// with U on top (B on top for U and F on top for D).
+const fn sticker_coords(face: Face, row: u8, col: u8) -> [u8; 3] {
+    match face {
+        Face::Up => [col, 2 - row, 2],
+        Face::Right => [2, col, 2 - row],
+        Face::Front => [col, 0, 2 - row],
+        Face::Down => [col, row, 0],
+        Face::Left => [0, 2 - col, 2 - row],
+        Face::Back => [2 - col, 2, 2 - row],
+    }
+}
+
 impl Cube<Position> {
     pub fn solved() -> Self {
         Cube::new([Rotation::Neutral; 20])
     }
 
+    /// The face each of the 54 stickers belongs to on a solved cube, i.e. its color. Faces are
+    /// listed in the order of [`Face::FACELET_ORDER`] and the stickers of each face row by row
+    /// (see `sticker_coords`). Centers never move.
+    pub fn stickers(&self) -> [Face; 54] {
+        let mut stickers = [Face::Up; 54];
+        for (f, face) in Face::FACELET_ORDER.into_iter().enumerate() {
+            for i in 0..9 {
+                let coords = sticker_coords(face, i / 3, i % 3);
+                stickers[f * 9 + i as usize] = if coords.iter().filter(|&&c| c == 1).count() == 2 {
+                    face
+                } else {
+                    let rot = self.cubelets[index(coords)];
+                    Face::from_normal(rot.inverse().rotate_vector(face.normal()))
+                };
+            }
+        }
+        stickers
+    }
+
+    /// The inverse of [`Cube::stickers`]. Returns `None` if the stickers can't be made by
+    /// taking a solved cube apart and putting its cubelets back, e.g. if a corner has two stickers
+    /// of the same color or a center is out of place. Whether the cube can be solved is not checked.
+    pub fn from_stickers(stickers: &[Face; 54]) -> Option<Self> {
+        // The faces and sticker indices of every cubelet
+        let mut cubelet_stickers = [[None; 3]; 20];
+        for (f, face) in Face::FACELET_ORDER.into_iter().enumerate() {
+            for i in 0..9 {
+                let coords = sticker_coords(face, i / 3, i % 3);
+                if coords.iter().filter(|&&c| c == 1).count() == 2 {
+                    if stickers[f * 9 + i as usize] != face {
+                        return None;
+                    }
+                    continue;
+                }
+                let slot = cubelet_stickers[index(coords)].iter_mut().find(|s| s.is_none()).unwrap();
+                *slot = Some((face, stickers[f * 9 + i as usize]));
+            }
+        }
+
+        let mut cubelets = [Rotation::Neutral; 20];
+        let mut placed = [false; 20];
+        for (i, stickers) in cubelet_stickers.iter().enumerate() {
+            let rot = Rotation::VARIANTS.into_iter().find(|rot| {
+                stickers.iter().flatten().all(|&(face, color)| {
+                    Face::from_normal(rot.inverse().rotate_vector(face.normal())) == color
+                })
+            })?;
+            let origin = rot.inverse().rotate_vector(coords(i).map(|c| c as i8 - 1)).map(|c| (c + 1) as u8);
+            if std::mem::replace(&mut placed[index(origin)], true) {
+                return None;
+            }
+            cubelets[i] = rot;
+        }
+        Some(Cube::new(cubelets))
+    }
+
+    /// The stickers as a string of face letters, e.g. `UUUUUUUUURRR...` for the solved cube
+    pub fn facelets(&self) -> String {
+        self.stickers().iter().map(|face| face.letter()).collect()
+    }
+
+    /// The inverse of [`Cube::facelets`]
+    pub fn from_facelets(facelets: &str) -> Option<Self> {
+        let faces: Vec<Face> = facelets.chars().map(Face::from_letter).collect::<Option<_>>()?;
+        Self::from_stickers(&faces.try_into().ok()?)
+    }
+
     pub fn turn_face<const FACE: usize>(self, rot: Rotation) -> Self {
         if rot == Rotation::Neutral { self } else {
             debug_assert!(FACE == 0 || FACE == 2, "{FACE}");
@@ -270,6 +366,42 @@ impl Cube<Position> {
         self.turn_face::<0>(rot1).turn_face::<2>(rot2)
     }
 
+    /// The same state seen with the whole cube turned by `rot`. Every cubelet moves to where `rot`
+    /// takes it and is turned along with it, so a face turn becomes a turn of the face that `rot`
+    /// brings it to and the solved cube stays solved.
+    pub fn reoriented(&self, rot: Rotation) -> Self {
+        let mut cubelets = [Rotation::Neutral; 20];
+        for (i, &r) in self.cubelets.iter().enumerate() {
+            let centered = coords(i).map(|c| c as i8 - 1);
+            let moved = rot.rotate_vector(centered).map(|c| (c + 1) as u8);
+            cubelets[index(moved)] = rot.inverse().compose(r).compose(rot);
+        }
+        Cube::new(cubelets)
+    }
+
+    /// The state seen in a mirror across the plane between the two faces of `axis`. Cubelets swap
+    /// sides and are turned the opposite way, so the mirror image of a word's state is the state
+    /// of the mirrored word (see [`Move::mirrored`]).
+    pub fn mirrored(&self, axis: Axis) -> Self {
+        let reflect = |mut v: [i8; 3]| {
+            v[axis as usize] = -v[axis as usize];
+            v
+        };
+        let units = [[1, 0, 0], [0, 1, 0], [0, 0, 1]];
+
+        let mut cubelets = [Rotation::Neutral; 20];
+        for (i, &r) in self.cubelets.iter().enumerate() {
+            let mut moved = coords(i);
+            moved[axis as usize] = 2 - moved[axis as usize];
+            // Reflecting, rotating and reflecting back is the rotation we are looking for
+            let mirrored = Rotation::VARIANTS.into_iter()
+                .find(|m| units.iter().all(|&u| m.rotate_vector(u) == reflect(r.rotate_vector(reflect(u)))))
+                .unwrap();
+            cubelets[index(moved)] = mirrored;
+        }
+        Cube::new(cubelets)
+    }
+
     pub fn by_id(&self, mutations: &[Rotation; 20]) -> Cube<Id> {
         Cube::new(mutations.iter()
             .enumerate()
@@ -318,6 +450,73 @@ impl<T: SortBy> Cube<T> {
 
 #[cfg(test)]
 mod tests {
+    #[test]
+    fn test_stickers() {
+        use super::*;
+        use crate::action::Turn;
+
+        let solved = Cube::solved().stickers();
+        for (i, face) in solved.iter().enumerate() {
+            assert_eq!(*face, Face::FACELET_ORDER[i / 9]);
+        }
+
+        // R brings the front stickers up
+        let stickers = Cube::solved().make_move(Turn::R).stickers();
+        for row in 0..3 {
+            assert_eq!(stickers[row * 3 + 2], Face::Front);
+            assert_eq!(stickers[row * 3], Face::Up);
+        }
+
+        let cube = [Turn::R, Turn::U, Turn::F2, Turn::L3, Turn::D, Turn::B, Turn::R2, Turn::U3]
+            .into_iter()
+            .fold(Cube::solved(), |cube, t| cube.make_move(t));
+        let stickers = cube.stickers();
+        for face in Face::FACELET_ORDER {
+            assert_eq!(stickers.iter().filter(|&&f| f == face).count(), 9);
+        }
+    }
+
+    #[test]
+    fn test_reoriented() {
+        use super::*;
+        use crate::action::{Action, Turn};
+
+        let turns: Vec<_> = Turn::ALL.iter().map(|&t| Cube::solved().make_move(t)).collect();
+        for rot in Rotation::VARIANTS {
+            assert_eq!(Cube::solved().reoriented(rot), Cube::solved());
+            for turn in turns.iter() {
+                assert!(turns.contains(&turn.reoriented(rot)), "{rot:?}");
+                assert_eq!(turn.reoriented(rot).reoriented(rot.inverse()), *turn);
+            }
+        }
+
+        // Turning the cube about the U axis takes R to B
+        assert_eq!(turns[Turn::R.index()].reoriented(Rotation::Z), turns[Turn::B.index()]);
+    }
+
+    #[test]
+    fn test_facelets() {
+        use super::*;
+        use crate::action::Turn;
+
+        let solved = Cube::solved().facelets();
+        assert_eq!(solved, "UUUUUUUUURRRRRRRRRFFFFFFFFFDDDDDDDDDLLLLLLLLLBBBBBBBBB");
+        assert_eq!(Cube::from_facelets(&solved), Some(Cube::solved()));
+
+        let cube = [Turn::R, Turn::U, Turn::F2, Turn::L3, Turn::D, Turn::B, Turn::R2, Turn::U3]
+            .into_iter()
+            .fold(Cube::solved(), |cube, t| cube.make_move(t));
+        assert_eq!(Cube::from_facelets(&cube.facelets()), Some(cube.clone()));
+        assert_eq!(cube.to_string().parse::<Cube<Position>>().unwrap(), cube);
+
+        // Swapping two stickers of a corner breaks it
+        let mut broken: Vec<char> = solved.chars().collect();
+        broken.swap(8, 9);
+        assert_eq!(Cube::from_facelets(&broken.into_iter().collect::<String>()), None);
+        assert_eq!(Cube::from_facelets("UUUU"), None);
+        assert!("AAAA".parse::<Cube<Position>>().is_err());
+    }
+
     #[test]
     fn test_move_back_and_forth() {
         use rand::{thread_rng, Rng};
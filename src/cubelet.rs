@@ -87,6 +87,26 @@ impl Rotation {
         unsafe { std::mem::transmute::<Self, u8>(self) as usize }
     }
 
+    /// A stable integer for the rotation. These will not change between releases and
+    /// `Rotation::VARIANTS[r.index()] == r` always holds.
+    #[inline]
+    pub const fn index(self) -> usize {
+        self.into_usize()
+    }
+
+    /// The inverse of [`Rotation::index`]
+    pub fn from_index(index: usize) -> Option<Self> {
+        Self::VARIANTS.get(index).copied()
+    }
+
+    /// Rotate a vector (relative to the center of the cube) the same way this rotation turns a
+    /// cubelet. Each axis is turned counterclockwise when looking down the axis from its positive
+    /// end, which is the same direction the faces turn.
+    pub(crate) const fn rotate_vector(self, v: [i8; 3]) -> [i8; 3] {
+        let [(axis1, turns1), (axis2, turns2)] = self.into_parts();
+        simple_rotate_vector(simple_rotate_vector(v, axis1, turns1), axis2, turns2)
+    }
+
     const fn simple_rotation(left: Self, axis: Axis, turns: u8) -> Self {
         if turns == 0 { left } else {
             let new = match (left, axis) {
@@ -242,7 +262,7 @@ impl Rotation {
                     arr[i] = Self::VARIANTS[j];
                     break;
                 }
-                j += 24;
+                j += 1;
             }
 
             i += 1;
@@ -281,6 +301,17 @@ impl Rotation {
     }
 }
 
+#[allow(non_snake_case)]
+const fn simple_rotate_vector(v: [i8; 3], axis: Axis, turns: u8) -> [i8; 3] {
+    let [X, Y, Z] = v;
+    match (axis, turns) {
+        (_, 0) => v,
+        (Axis::X, n) => simple_rotate_vector([X, -Z, Y], axis, n - 1),
+        (Axis::Y, n) => simple_rotate_vector([Z, Y, -X], axis, n - 1),
+        (Axis::Z, n) => simple_rotate_vector([-Y, X, Z], axis, n - 1),
+    }
+}
+
 /// The faces of the Rubiks' cube
 #[repr(u8)]
 #[derive(Clone, Copy, Debug, PartialEq, Eq)]
@@ -299,6 +330,72 @@ pub enum Face {
     Up,
 }
 
+impl Face {
+    /// The order of the faces in facelet strings and sticker encodings: U, R, F, D, L, B
+    pub const FACELET_ORDER: [Face; 6] = [Self::Up, Self::Right, Self::Front, Self::Down, Self::Left, Self::Back];
+
+    /// The letter for the face in standard notation
+    pub const fn letter(self) -> char {
+        match self {
+            Self::Left => 'L',
+            Self::Right => 'R',
+            Self::Front => 'F',
+            Self::Back => 'B',
+            Self::Down => 'D',
+            Self::Up => 'U',
+        }
+    }
+
+    /// The inverse of [`Face::letter`]
+    pub const fn from_letter(letter: char) -> Option<Self> {
+        match letter {
+            'L' => Some(Self::Left),
+            'R' => Some(Self::Right),
+            'F' => Some(Self::Front),
+            'B' => Some(Self::Back),
+            'D' => Some(Self::Down),
+            'U' => Some(Self::Up),
+            _ => None,
+        }
+    }
+
+    /// Position of the face in [`Face::FACELET_ORDER`]
+    pub const fn facelet_index(self) -> usize {
+        match self {
+            Self::Up => 0,
+            Self::Right => 1,
+            Self::Front => 2,
+            Self::Down => 3,
+            Self::Left => 4,
+            Self::Back => 5,
+        }
+    }
+
+    /// The outward unit vector of the face
+    pub(crate) const fn normal(self) -> [i8; 3] {
+        match self {
+            Self::Left => [-1, 0, 0],
+            Self::Right => [1, 0, 0],
+            Self::Front => [0, -1, 0],
+            Self::Back => [0, 1, 0],
+            Self::Down => [0, 0, -1],
+            Self::Up => [0, 0, 1],
+        }
+    }
+
+    pub(crate) const fn from_normal(v: [i8; 3]) -> Self {
+        match v {
+            [-1, 0, 0] => Self::Left,
+            [1, 0, 0] => Self::Right,
+            [0, -1, 0] => Self::Front,
+            [0, 1, 0] => Self::Back,
+            [0, 0, -1] => Self::Down,
+            [0, 0, 1] => Self::Up,
+            _ => panic!("not the normal of a face"),
+        }
+    }
+}
+
 /// The unique facelet colors
 #[repr(u8)]
 #[derive(Clone, Copy, Debug, PartialEq, Eq)]
@@ -427,3 +524,18 @@ impl Cubelet {
     }
 }
 
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_inverses() {
+        for r in Rotation::VARIANTS {
+            assert_eq!(r.compose(r.inverse()), Rotation::Neutral, "{r:?}");
+            assert_eq!(r.inverse().compose(r), Rotation::Neutral, "{r:?}");
+        }
+        assert_eq!(Rotation::X.inverse(), Rotation::X3);
+        assert_eq!(Rotation::XY.inverse().inverse(), Rotation::XY);
+    }
+}
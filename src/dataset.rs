@@ -0,0 +1,426 @@
+//! Batches of encoded cubes and labels for training, read straight from a [`Book`] or a
+//! [`CompactBook`]. Cubes are read in the book's own order, split between workers, mixed in a
+//! shuffle buffer and encoded with [`crate::encode`], so only the buffer is ever held in memory.
+//!
+//! ```ignore
+//! let dataset = Dataset::new(&book)
+//!     .batch_size(256)
+//!     .shuffle(1 << 16, 7)
+//!     .epochs(10)
+//!     .shard(worker_id, num_workers)
+//!     .labels(Labels::BestActions);
+//! for batch in dataset.iter() {
+//!     let batch = batch?;
+//!     // batch.states is 256 x 20 rotation indices, batch.labels 256 x 18 zeros and ones
+//! }
+//! ```
+
+#![allow(private_bounds)]
+use std::io;
+
+use rand::{rngs::StdRng, seq::IteratorRandom, Rng, SeedableRng};
+
+use crate::{
+    action::Action,
+    book::{Book, Int, Packable},
+    compact::CompactBook,
+    encode,
+    sample::Sample,
+};
+
+/// How the cubes of a batch are encoded
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum States {
+    /// [`encode::rotation_indices`], `u8` of shape `(20,)` per cube
+    #[default]
+    RotationIndices,
+    /// [`encode::rotations_one_hot`], `f32` of shape `(20, 24)` per cube
+    RotationsOneHot,
+    /// [`encode::sticker_indices`], `u8` of shape `(54,)` per cube
+    StickerIndices,
+    /// [`encode::stickers_one_hot`], `f32` of shape `(54, 6)` per cube
+    StickersOneHot,
+}
+
+/// What a batch is labelled with. Labels made from best actions skip the cubes that don't have
+/// any recorded.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Labels {
+    /// The depth of each cube as an `f32`, for regression
+    #[default]
+    Depth,
+    /// Every best action of each cube as `f32` zeros and ones of shape `(A::ALL.len(),)`, for
+    /// multi-label classification or, once normalized, as a target distribution
+    BestActions,
+    /// The [`Action::index`] of one best action of each cube as a `u8`, picked at random when there
+    /// are several, for classification
+    BestAction,
+}
+
+impl Labels {
+    fn needs_best(self) -> bool {
+        self != Labels::Depth
+    }
+}
+
+/// A flat, row-major buffer
+#[derive(Clone, Debug, PartialEq)]
+pub enum Array {
+    U8(Vec<u8>),
+    F32(Vec<f32>),
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Batch {
+    /// The number of cubes in the batch
+    pub len: usize,
+    /// The epoch the batch is from, counting from 0
+    pub epoch: usize,
+    pub states: Array,
+    /// The shape of the states of one cube
+    pub state_shape: Vec<usize>,
+    pub labels: Array,
+    /// The shape of the labels of one cube, empty for a single number
+    pub label_shape: Vec<usize>,
+}
+
+#[derive(Clone)]
+enum Source<D, A> {
+    Book(Book<D, A>),
+    Compact(Box<CompactBook<D, A>>),
+}
+
+type Samples<'a, D, A> = Box<dyn Iterator<Item = io::Result<Sample<D, A>>> + 'a>;
+
+/// A description of how to read a book for training, which [`Dataset::iter`] follows. Every setting
+/// has a builder method, and the defaults read the book once, unshuffled, in batches of 32 labelled
+/// with depths.
+#[derive(Clone)]
+pub struct Dataset<D, A> {
+    source: Source<D, A>,
+    batch_size: usize,
+    drop_last: bool,
+    shuffle_buffer: usize,
+    seed: u64,
+    epochs: Option<usize>,
+    worker: (usize, usize),
+    states: States,
+    labels: Labels,
+}
+
+impl<D: Int, A: Packable + Action> Dataset<D, A> {
+    /// A dataset over every cube in `book`
+    pub fn new(book: &Book<D, A>) -> Self {
+        Self::from_source(Source::Book(book.clone()))
+    }
+
+    /// A dataset over every cube in a compact book. Its rows are already in no particular order, so
+    /// a smaller shuffle buffer does as well as for a [`Book`].
+    pub fn from_compact(book: &CompactBook<D, A>) -> Self {
+        Self::from_source(Source::Compact(Box::new(book.clone())))
+    }
+
+    fn from_source(source: Source<D, A>) -> Self {
+        Self {
+            source,
+            batch_size: 32,
+            drop_last: false,
+            shuffle_buffer: 0,
+            seed: 0,
+            epochs: Some(1),
+            worker: (0, 1),
+            states: States::default(),
+            labels: Labels::default(),
+        }
+    }
+
+    pub fn batch_size(self, batch_size: usize) -> Self {
+        assert!(batch_size > 0, "Batch size must be at least 1");
+        Self { batch_size, ..self }
+    }
+
+    /// Leave out the last batch of each epoch if it is smaller than the others
+    pub fn drop_last(self, drop_last: bool) -> Self {
+        Self { drop_last, ..self }
+    }
+
+    /// Mix the cubes in a buffer of `buffer_size` before they are batched. Each epoch and worker
+    /// shuffles differently, but the same seed always gives the same batches.
+    pub fn shuffle(self, buffer_size: usize, seed: u64) -> Self {
+        Self { shuffle_buffer: buffer_size, seed, ..self }
+    }
+
+    /// Read the book this many times
+    pub fn epochs(self, epochs: usize) -> Self {
+        Self { epochs: Some(epochs), ..self }
+    }
+
+    /// Read the book again and again, for as long as batches are asked for
+    pub fn repeat(self) -> Self {
+        Self { epochs: None, ..self }
+    }
+
+    /// Only read every `num_workers`th cube, starting from the `worker_id`th, in the book's order.
+    /// The workers of one dataset together read every cube exactly once per epoch.
+    pub fn shard(self, worker_id: usize, num_workers: usize) -> Self {
+        assert!(worker_id < num_workers, "Worker {worker_id} is out of range for {num_workers} workers");
+        Self { worker: (worker_id, num_workers), ..self }
+    }
+
+    pub fn states(self, states: States) -> Self {
+        Self { states, ..self }
+    }
+
+    pub fn labels(self, labels: Labels) -> Self {
+        Self { labels, ..self }
+    }
+
+    /// The batches of every epoch, in order
+    pub fn iter(&self) -> Batches<'_, D, A> {
+        Batches {
+            dataset: self,
+            epoch: 0,
+            samples: None,
+            buffer: vec![],
+            rng: self.epoch_rng(0),
+            epoch_gave_batch: false,
+        }
+    }
+
+    /// This worker's cubes in the book's order. A book is read in full and every other worker's
+    /// cubes are skipped, but a compact book's rows are only read for this worker.
+    fn samples(&self) -> Samples<'_, D, A> {
+        let (worker_id, num_workers) = self.worker;
+        match &self.source {
+            Source::Book(book) => Box::new(book.entries()
+                .enumerate()
+                .filter(move |(ordinal, _)| ordinal % num_workers == worker_id)
+                .map(|(_, entry)| entry)),
+            Source::Compact(book) => Box::new((worker_id..book.len())
+                .step_by(num_workers)
+                .map(|row| Ok(book.row(row).unwrap()))),
+        }
+    }
+
+    fn epoch_rng(&self, epoch: usize) -> StdRng {
+        let (worker_id, num_workers) = self.worker;
+        let stream = (epoch as u64).wrapping_mul(num_workers as u64).wrapping_add(worker_id as u64);
+        StdRng::seed_from_u64(self.seed ^ stream.wrapping_mul(0x9e3779b97f4a7c15))
+    }
+}
+
+/// The iterator returned by [`Dataset::iter`]
+pub struct Batches<'a, D, A: Action> {
+    dataset: &'a Dataset<D, A>,
+    epoch: usize,
+    samples: Option<Samples<'a, D, A>>,
+    buffer: Vec<Sample<D, A>>,
+    rng: StdRng,
+    // Whether this epoch has given a batch yet. An epoch that gives none, because the book is empty
+    // or too small for a full batch with `drop_last`, stops a dataset that repeats forever.
+    epoch_gave_batch: bool,
+}
+
+impl<D: Int, A: Packable + Action> Batches<'_, D, A> {
+    /// The next cube of this epoch, or `None` once the epoch is over
+    fn next_sample(&mut self) -> Option<io::Result<Sample<D, A>>> {
+        let dataset = self.dataset;
+        let samples = self.samples.get_or_insert_with(|| dataset.samples());
+        while self.buffer.len() < dataset.shuffle_buffer.max(1) {
+            let sample = match samples.next() {
+                Some(Ok(sample)) => sample,
+                Some(Err(e)) => return Some(Err(e)),
+                None => break,
+            };
+            if !dataset.labels.needs_best() || !sample.1.best.is_empty() {
+                self.buffer.push(sample);
+            }
+        }
+        if self.buffer.is_empty() {
+            return None;
+        }
+        let i = self.rng.gen_range(0..self.buffer.len());
+        Some(Ok(self.buffer.swap_remove(i)))
+    }
+
+    /// Move on to the next epoch, returning false if there isn't one
+    fn next_epoch(&mut self) -> bool {
+        if !self.epoch_gave_batch {
+            return false;
+        }
+        self.epoch += 1;
+        self.samples = None;
+        self.rng = self.dataset.epoch_rng(self.epoch);
+        self.epoch_gave_batch = false;
+        true
+    }
+
+    fn encode(&mut self, samples: Vec<Sample<D, A>>) -> Batch {
+        let cubes = samples.iter().map(|(cube, _)| cube);
+        let (states, state_shape) = match self.dataset.states {
+            States::RotationIndices => (Array::U8(encode::rotation_indices(cubes)), vec![20]),
+            States::RotationsOneHot => (Array::F32(encode::rotations_one_hot(cubes)), encode::ROTATIONS_SHAPE.to_vec()),
+            States::StickerIndices => (Array::U8(encode::sticker_indices(cubes)), vec![54]),
+            States::StickersOneHot => (Array::F32(encode::stickers_one_hot(cubes)), encode::STICKERS_SHAPE.to_vec()),
+        };
+        let (labels, label_shape) = match self.dataset.labels {
+            Labels::Depth => {
+                let depths = samples.iter().map(|(_, entry)| entry.depth.into() as f32).collect();
+                (Array::F32(depths), vec![])
+            }
+            Labels::BestActions => {
+                let best = samples.iter()
+                    .flat_map(|(_, entry)| A::ALL.iter().map(|&a| entry.best.contains(a) as u8 as f32))
+                    .collect();
+                (Array::F32(best), vec![A::ALL.len()])
+            }
+            Labels::BestAction => {
+                let rng = &mut self.rng;
+                let best = samples.iter().map(|(_, entry)| entry.best.iter().choose(rng).unwrap());
+                (Array::U8(encode::action_indices(best)), vec![])
+            }
+        };
+        Batch { len: samples.len(), epoch: self.epoch, states, state_shape, labels, label_shape }
+    }
+}
+
+impl<D: Int, A: Packable + Action> Iterator for Batches<'_, D, A> {
+    type Item = io::Result<Batch>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if self.dataset.epochs.is_some_and(|epochs| self.epoch >= epochs) {
+                return None;
+            }
+
+            let mut samples = Vec::with_capacity(self.dataset.batch_size);
+            while samples.len() < self.dataset.batch_size {
+                match self.next_sample() {
+                    Some(Ok(sample)) => samples.push(sample),
+                    Some(Err(e)) => return Some(Err(e)),
+                    None => break,
+                }
+            }
+            let full = samples.len() == self.dataset.batch_size;
+            if full || (!samples.is_empty() && !self.dataset.drop_last) {
+                self.epoch_gave_batch = true;
+                let batch = self.encode(samples);
+                if !full {
+                    self.next_epoch();
+                }
+                return Some(Ok(batch));
+            }
+            if !self.next_epoch() {
+                return None;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+
+    use super::*;
+    use crate::{action::{ActionSet, Canonical, Turn}, book::Entry, word::Word};
+
+    fn book() -> Book<u16, Turn> {
+        let book = Book::in_memory();
+        book.insert(Word::new(), 0).unwrap();
+        for (t, _) in Canonical::START.successors::<Turn>() {
+            let mut word = Word::new();
+            word.extend([t]);
+            let mut best = ActionSet::new();
+            best.insert(word.inverse().actions()[0]);
+            book.insert_entry(word.cube(), Entry { depth: 1, best, solution: None }).unwrap();
+            for (u, _) in Canonical::START.successors::<Turn>().filter(|(u, _)| u.index() / 3 != t.index() / 3) {
+                let mut word = word.clone();
+                word.extend([u]);
+                book.insert(word, 2).unwrap();
+            }
+        }
+        book
+    }
+
+    // The rotation indices of every cube in every batch
+    fn states(batches: impl Iterator<Item = io::Result<Batch>>) -> Vec<Vec<u8>> {
+        batches
+            .flat_map(|batch| match batch.unwrap().states {
+                Array::U8(states) => states.chunks(20).map(<[u8]>::to_vec).collect::<Vec<_>>(),
+                Array::F32(_) => unreachable!(),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_dataset() {
+        let book = book();
+        let n = book.len();
+
+        // Every cube once per epoch, with only the last batch of each epoch short
+        let dataset = Dataset::new(&book).batch_size(50).shuffle(64, 1).epochs(2);
+        let batches: Vec<_> = dataset.iter().map(Result::unwrap).collect();
+        assert_eq!(batches.len(), 2 * n.div_ceil(50));
+        assert_eq!(batches.iter().map(|batch| batch.len).sum::<usize>(), 2 * n);
+        assert_eq!(batches[n.div_ceil(50) - 1].len, n % 50);
+        assert_eq!(batches.last().unwrap().epoch, 1);
+        let first = states(dataset.iter().take(n.div_ceil(50)));
+        assert_eq!(first.iter().collect::<HashSet<_>>().len(), n);
+        assert_eq!(first, states(dataset.iter().take(n.div_ceil(50))));
+        assert_ne!(first, states(dataset.iter().skip(n.div_ceil(50))));
+        assert_ne!(first, states(Dataset::new(&book).batch_size(50).iter()));
+
+        // Workers split the cubes between them
+        let shards: Vec<_> = (0..3)
+            .map(|worker| states(Dataset::new(&book).shuffle(16, 1).shard(worker, 3).iter()))
+            .collect();
+        assert_eq!(shards.iter().map(Vec::len).sum::<usize>(), n);
+        assert_eq!(shards.iter().flatten().collect::<HashSet<_>>().len(), n);
+
+        let dropped = Dataset::new(&book).batch_size(50).drop_last(true).repeat();
+        assert!(dropped.iter().take(10).all(|batch| batch.unwrap().len == 50));
+
+        // Labels from best actions only use the cubes that have them
+        let batch = Dataset::new(&book).batch_size(100).labels(Labels::BestActions).states(States::StickersOneHot)
+            .iter().next().unwrap().unwrap();
+        assert_eq!((batch.len, batch.state_shape.as_slice(), batch.label_shape.as_slice()), (18, &[54, 6][..], &[18][..]));
+        match batch.labels {
+            Array::F32(labels) => assert_eq!(labels.iter().sum::<f32>(), 18.0),
+            Array::U8(_) => unreachable!(),
+        }
+        let batch = Dataset::new(&book).batch_size(100).labels(Labels::BestAction).iter().next().unwrap().unwrap();
+        assert_eq!(batch.label_shape, Vec::<usize>::new());
+        assert!(matches!(batch.labels, Array::U8(labels) if labels.len() == 18));
+
+        assert_eq!(Dataset::new(&Book::<u16, Turn>::in_memory()).repeat().iter().count(), 0);
+        let small = Book::<u16, Turn>::in_memory();
+        small.insert(Word::new(), 0).unwrap();
+        assert_eq!(Dataset::new(&small).batch_size(4).drop_last(true).repeat().iter().count(), 0);
+        assert_eq!(Dataset::new(&small).batch_size(4).repeat().iter().take(3).count(), 3);
+    }
+
+    #[test]
+    fn test_compact_dataset() {
+        let name = std::env::temp_dir().join(format!("rubiks_test_compact_dataset_{}.bin", std::process::id()));
+        let name = name.to_str().unwrap();
+        let book = book();
+        book.compact(name).unwrap();
+        let compact: CompactBook<u16, Turn> = CompactBook::open(name).unwrap();
+        let batches: Vec<_> = Dataset::from_compact(&compact).batch_size(64).shuffle(32, 3).iter()
+            .map(Result::unwrap)
+            .collect();
+        assert_eq!(batches.iter().map(|batch| batch.len).sum::<usize>(), book.len());
+        match &batches[0].labels {
+            Array::F32(depths) => assert!(depths.iter().all(|&d| d <= 2.0)),
+            Array::U8(_) => unreachable!(),
+        }
+
+        // Workers read only their own rows, and together every row once
+        let shards: Vec<_> = (0..4)
+            .map(|worker| states(Dataset::from_compact(&compact).shuffle(16, 1).shard(worker, 4).iter()))
+            .collect();
+        assert!(shards.iter().all(|shard| shard.len() == book.len() / 4 || shard.len() == book.len() / 4 + 1));
+        assert_eq!(shards.iter().flatten().collect::<HashSet<_>>().len(), book.len());
+        let _ = std::fs::remove_file(name);
+    }
+}
@@ -0,0 +1,111 @@
+//! Batch encoders for feeding cubes and actions to a model. Every encoder writes a flat, row-major
+//! buffer that can be reshaped on the Python side, e.g. `np.frombuffer(buf, np.float32).reshape(-1,
+//! 20, 24)`. The layouts are built on [`Rotation::index`], [`Action::index`] and
+//! [`Cube::stickers`], none of which will change between releases.
+
+use crate::{
+    action::Action,
+    cube::{Cube, Position},
+    cubelet::Rotation,
+};
+
+/// Shape of a single cube encoded by [`rotations_one_hot`]: one row per cubelet, one column per
+/// rotation
+pub const ROTATIONS_SHAPE: [usize; 2] = [20, 24];
+
+/// Shape of a single cube encoded by [`stickers_one_hot`]: one row per sticker, one column per
+/// face in [`crate::cubelet::Face::FACELET_ORDER`]
+pub const STICKERS_SHAPE: [usize; 2] = [54, 6];
+
+/// The rotation index of each cubelet, 20 bytes per cube
+pub fn rotation_indices<'a>(cubes: impl IntoIterator<Item = &'a Cube<Position>>) -> Vec<u8> {
+    cubes.into_iter()
+        .flat_map(|cube| cube.cubelets.iter().map(|rot| rot.index() as u8))
+        .collect()
+}
+
+/// One-hot rotations of each cubelet, 20x24 values per cube
+pub fn rotations_one_hot<'a>(cubes: impl IntoIterator<Item = &'a Cube<Position>>) -> Vec<f32> {
+    let indices = rotation_indices(cubes);
+    one_hot(&indices, Rotation::VARIANTS.len())
+}
+
+/// The face index of each sticker, 54 bytes per cube
+pub fn sticker_indices<'a>(cubes: impl IntoIterator<Item = &'a Cube<Position>>) -> Vec<u8> {
+    cubes.into_iter()
+        .flat_map(|cube| cube.stickers().map(|face| face.facelet_index() as u8))
+        .collect()
+}
+
+/// One-hot colors of each sticker, 54x6 values per cube
+pub fn stickers_one_hot<'a>(cubes: impl IntoIterator<Item = &'a Cube<Position>>) -> Vec<f32> {
+    let indices = sticker_indices(cubes);
+    one_hot(&indices, STICKERS_SHAPE[1])
+}
+
+/// The index of each action, one byte per action
+pub fn action_indices<A: Action>(actions: impl IntoIterator<Item = A>) -> Vec<u8> {
+    actions.into_iter().map(|a| a.index() as u8).collect()
+}
+
+/// One-hot actions, `A::ALL.len()` values per action
+pub fn actions_one_hot<A: Action>(actions: impl IntoIterator<Item = A>) -> Vec<f32> {
+    one_hot(&action_indices(actions), A::ALL.len())
+}
+
+fn one_hot(indices: &[u8], width: usize) -> Vec<f32> {
+    let mut buf = vec![0.0; indices.len() * width];
+    for (row, &i) in indices.iter().enumerate() {
+        buf[row * width + i as usize] = 1.0;
+    }
+    buf
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::action::{Move, QuarterTurn, Turn};
+
+    #[test]
+    fn test_action_indices_are_stable() {
+        fn check<A: Action + std::fmt::Debug>() {
+            for (i, a) in A::ALL.iter().enumerate() {
+                assert_eq!(a.index(), i, "{a:?}");
+                assert_eq!(A::from_index(i), Some(*a));
+            }
+            assert_eq!(A::from_index(A::ALL.len()), None);
+        }
+        check::<Move>();
+        check::<Turn>();
+        check::<QuarterTurn>();
+
+        // The layout the notebooks were written against
+        let notebook = ["L", "L2", "L'", "R", "R2", "R'", "F", "F2", "F'", "B", "B2", "B'", "D", "D2", "D'", "U", "U2", "U'"];
+        let names: Vec<_> = Turn::ALL.iter().map(|t| t.to_string()).collect();
+        assert_eq!(names, notebook);
+
+        for (i, rot) in Rotation::VARIANTS.iter().enumerate() {
+            assert_eq!(rot.index(), i);
+            assert_eq!(Rotation::from_index(i), Some(*rot));
+        }
+    }
+
+    #[test]
+    fn test_encodings() {
+        let cubes = [Cube::solved(), Cube::solved().make_move(Turn::R)];
+
+        let rotations = rotations_one_hot(&cubes);
+        assert_eq!(rotations.len(), 2 * 20 * 24);
+        assert!(rotations.chunks(24).all(|row| row.iter().sum::<f32>() == 1.0));
+        assert!(rotations[..480].chunks(24).all(|row| row[0] == 1.0));
+
+        let stickers = stickers_one_hot(&cubes);
+        assert_eq!(stickers.len(), 2 * 54 * 6);
+        assert_eq!(sticker_indices(&cubes[..1]), (0..54).map(|i| i / 9).collect::<Vec<u8>>());
+
+        let actions = actions_one_hot([Turn::L, Turn::U3]);
+        assert_eq!(actions.len(), 2 * 18);
+        assert_eq!(actions[0], 1.0);
+        assert_eq!(actions[18 + 17], 1.0);
+    }
+}
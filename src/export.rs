@@ -0,0 +1,627 @@
+//! Writing books out in formats other tools can read. NumPy shards hold one row per cube, using the
+//! layouts in [`crate::encode`], so a training loop can load them with `np.load`. CSV and JSON Lines
+//! are streamed one cube per line and can be read back into a book.
+
+#![allow(private_bounds)]
+use std::{
+    borrow::Borrow,
+    fs::{self, File},
+    io::{self, BufRead, BufReader, BufWriter, Read, Write},
+    path::{Path, PathBuf},
+};
+
+use rand::{rngs::StdRng, seq::SliceRandom, Rng, SeedableRng};
+use zip::{write::FileOptions, CompressionMethod, ZipWriter};
+
+use crate::{
+    action::{Action, ActionSet},
+    book::{Book, Entry, Int, Packable},
+    cube::{Cube, Position},
+    cubelet::Rotation,
+    encode,
+    view::Notation,
+    word::Word,
+};
+
+/// How each cube is written
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum StateEncoding {
+    /// The rotation index of each cubelet, shape `(n, 20)`
+    #[default]
+    Rotations,
+    /// The face index of each sticker, shape `(n, 54)`
+    Stickers,
+}
+
+/// The order cubes are written in
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Order {
+    /// The order of the book's keys, which is fast but groups similar cubes together
+    #[default]
+    Key,
+    /// Closest to solved first. The book is read once per depth.
+    Depth,
+    /// Shuffled across every shard using the given seed. Each cube is first sent to a random shard
+    /// in a temporary file next to the shards, then each shard is shuffled on its own, so only one
+    /// shard is ever held in memory.
+    Shuffled(u64),
+}
+
+/// How the arrays of a shard are stored
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum NpyFormat {
+    /// One `shard-00000.npz` per shard holding `states`, `depths` and `best`
+    #[default]
+    Npz,
+    /// Separate `shard-00000-states.npy` files and so on
+    Npy,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct NpyOptions {
+    /// The number of cubes in each shard. Only the last shard can be smaller.
+    pub shard_size: usize,
+    pub states: StateEncoding,
+    /// Also write the best actions of each cube as a `(n, A::ALL.len())` array of zeros and ones
+    pub best_actions: bool,
+    pub order: Order,
+    pub format: NpyFormat,
+}
+
+impl Default for NpyOptions {
+    fn default() -> Self {
+        Self {
+            shard_size: 1 << 20,
+            states: StateEncoding::default(),
+            best_actions: false,
+            order: Order::default(),
+            format: NpyFormat::default(),
+        }
+    }
+}
+
+/// Write `book` to `dir` as NumPy shards, returning the paths of the files written. Depths are
+/// written with the book's depth type, e.g. `uint16` for `u16`. Any `shard-*` files already in
+/// `dir` are deleted first, so shards from an earlier export are never mixed in with these.
+pub fn write_npy<D: Int, A: Packable + Action>(
+    book: &Book<D, A>,
+    dir: impl AsRef<Path>,
+    options: &NpyOptions,
+) -> io::Result<Vec<PathBuf>> {
+    if options.shard_size == 0 {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, "Shard size must be at least 1"));
+    }
+    let dir = dir.as_ref();
+    fs::create_dir_all(dir)?;
+    for file in fs::read_dir(dir)? {
+        let file = file?;
+        if file.file_name().to_string_lossy().starts_with("shard-") && file.file_type()?.is_file() {
+            fs::remove_file(file.path())?;
+        }
+    }
+
+    let mut writer = ShardWriter { dir, options, shard: Shard::default(), index: 0, paths: vec![] };
+    match options.order {
+        Order::Key => {
+            for entry in book.entries() {
+                let (cube, entry) = entry?;
+                writer.push(cube, entry.depth, entry.best)?;
+            }
+        }
+        Order::Depth => {
+            for &depth in book.depth_counts()?.keys() {
+                for entry in book.entries() {
+                    let (cube, entry) = entry?;
+                    if entry.depth.into() == depth {
+                        writer.push(cube, entry.depth, entry.best)?;
+                    }
+                }
+            }
+        }
+        Order::Shuffled(seed) => write_shuffled(book, &mut writer, seed)?,
+    }
+    writer.finish()
+}
+
+// A cube as it is held in a temporary file while shuffling: 20 rotation indices, the depth and the
+// best actions as little endian u64s
+const RECORD_LEN: usize = 20 + 8 + 8;
+
+fn write_shuffled<D: Int, A: Packable + Action>(
+    book: &Book<D, A>,
+    writer: &mut ShardWriter,
+    seed: u64,
+) -> io::Result<()> {
+    let mut rng = StdRng::seed_from_u64(seed);
+    let total = book.depth_counts()?.values().sum::<u64>() as usize;
+    let shard_size = writer.options.shard_size;
+    let n_shards = total.div_ceil(shard_size);
+    let temp_path = |i: usize| writer.dir.join(format!(".shard-{i:05}.tmp"));
+
+    // Every way of splitting the cubes into shards of these sizes is equally likely
+    let mut remaining: Vec<usize> = (0..n_shards)
+        .map(|i| shard_size.min(total - i * shard_size))
+        .collect();
+    let mut temps = (0..n_shards)
+        .map(|i| File::create(temp_path(i)).map(BufWriter::new))
+        .collect::<io::Result<Vec<_>>>()?;
+
+    let mut left = total;
+    for entry in book.entries() {
+        let (cube, entry) = entry?;
+        if left == 0 {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "Book has more cubes than its depth counts"));
+        }
+        let mut pick = rng.gen_range(0..left);
+        let shard = remaining.iter().position(|&r| {
+            if pick < r { true } else { pick -= r; false }
+        }).unwrap();
+        remaining[shard] -= 1;
+        left -= 1;
+
+        let mut record = [0; RECORD_LEN];
+        for (byte, rot) in record.iter_mut().zip(cube.cubelets.iter()) {
+            *byte = rot.index() as u8;
+        }
+        record[20..28].copy_from_slice(&entry.depth.into().to_le_bytes());
+        record[28..].copy_from_slice(&entry.best.bits().to_le_bytes());
+        temps[shard].write_all(&record)?;
+    }
+    for temp in temps {
+        temp.into_inner().map_err(|e| e.into_error())?.sync_all()?;
+    }
+
+    for i in 0..n_shards {
+        let mut records = vec![];
+        BufReader::new(File::open(temp_path(i))?).read_to_end(&mut records)?;
+        let mut records: Vec<&[u8]> = records.chunks(RECORD_LEN).collect();
+        records.shuffle(&mut rng);
+
+        for record in records {
+            let cubelets = std::array::from_fn(|j| Rotation::from_index(record[j] as usize).unwrap());
+            let depth = depth_from_u64::<D>(u64::from_le_bytes(record[20..28].try_into().unwrap())).unwrap();
+            let best = ActionSet::<A>::from_bits(u64::from_le_bytes(record[28..].try_into().unwrap()));
+            writer.push(Cube::new(cubelets), depth, best)?;
+        }
+        fs::remove_file(temp_path(i))?;
+    }
+    Ok(())
+}
+
+#[derive(Default)]
+struct Shard {
+    cubes: Vec<Cube<Position>>,
+    depths: Vec<u8>,
+    depth_bytes: usize,
+    best: Vec<u8>,
+    n_actions: usize,
+}
+
+struct ShardWriter<'a> {
+    dir: &'a Path,
+    options: &'a NpyOptions,
+    shard: Shard,
+    index: usize,
+    paths: Vec<PathBuf>,
+}
+
+impl ShardWriter<'_> {
+    fn push<D: Int, A: Action>(&mut self, cube: Cube<Position>, depth: D, best: ActionSet<A>) -> io::Result<()> {
+        self.shard.cubes.push(cube);
+        self.shard.depths.extend_from_slice(depth.to_bytes().borrow());
+        self.shard.depth_bytes = D::BYTES;
+        self.shard.n_actions = A::ALL.len();
+        if self.options.best_actions {
+            self.shard.best.extend(A::ALL.iter().map(|&a| best.contains(a) as u8));
+        }
+        if self.shard.cubes.len() == self.options.shard_size {
+            self.flush()?;
+        }
+        Ok(())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        let shard = std::mem::take(&mut self.shard);
+        let n = shard.cubes.len();
+        let (states, width) = match self.options.states {
+            StateEncoding::Rotations => (encode::rotation_indices(&shard.cubes), 20),
+            StateEncoding::Stickers => (encode::sticker_indices(&shard.cubes), 54),
+        };
+
+        let mut arrays = vec![
+            ("states", npy(&states, "|u1", &[n, width])),
+            ("depths", npy(&shard.depths, &format!("<u{}", shard.depth_bytes), &[n])),
+        ];
+        if self.options.best_actions {
+            arrays.push(("best", npy(&shard.best, "|u1", &[n, shard.n_actions])));
+        }
+
+        let name = format!("shard-{:05}", self.index);
+        match self.options.format {
+            NpyFormat::Npz => {
+                let path = self.dir.join(format!("{name}.npz"));
+                let mut zip = ZipWriter::new(File::create(&path)?);
+                let options = FileOptions::default().compression_method(CompressionMethod::Stored);
+                for (array, bytes) in arrays {
+                    zip.start_file(format!("{array}.npy"), options)?;
+                    zip.write_all(&bytes)?;
+                }
+                zip.finish()?;
+                self.paths.push(path);
+            }
+            NpyFormat::Npy => {
+                for (array, bytes) in arrays {
+                    let path = self.dir.join(format!("{name}-{array}.npy"));
+                    fs::write(&path, bytes)?;
+                    self.paths.push(path);
+                }
+            }
+        }
+        self.index += 1;
+        Ok(())
+    }
+
+    fn finish(mut self) -> io::Result<Vec<PathBuf>> {
+        if !self.shard.cubes.is_empty() {
+            self.flush()?;
+        }
+        Ok(self.paths)
+    }
+}
+
+/// A version 1.0 `.npy` file holding `data`, which must already be in the layout given by `descr`
+/// and `shape`
+fn npy(data: &[u8], descr: &str, shape: &[usize]) -> Vec<u8> {
+    let shape = match shape {
+        [n] => format!("({n},)"),
+        _ => format!("({})", shape.iter().map(|d| d.to_string()).collect::<Vec<_>>().join(", ")),
+    };
+    let mut header = format!("{{'descr': '{descr}', 'fortran_order': False, 'shape': {shape}, }}");
+    // The magic string, version and header length take 10 bytes and the whole preamble is padded
+    // to a multiple of 64, ending with a newline
+    let len = (10 + header.len() + 1).div_ceil(64) * 64 - 10;
+    header.extend(std::iter::repeat_n(' ', len - header.len() - 1));
+    header.push('\n');
+
+    let mut bytes = Vec::with_capacity(10 + header.len() + data.len());
+    bytes.extend_from_slice(b"\x93NUMPY\x01\x00");
+    bytes.extend_from_slice(&(header.len() as u16).to_le_bytes());
+    bytes.extend_from_slice(header.as_bytes());
+    bytes.extend_from_slice(data);
+    bytes
+}
+
+/// How each cube is written in CSV and JSON Lines
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum TextState {
+    /// The 54 sticker letters of [`Cube::facelets`]
+    #[default]
+    Facelets,
+    /// The 20 rotation letters of the cube's `Display`
+    Rotations,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct TextOptions {
+    pub states: TextState,
+    /// Also write the stored solution of each cube, empty or `null` when there isn't one
+    pub words: bool,
+}
+
+/// Write `book` as CSV with a `state,depth` header, plus a `word` column if asked for, returning the
+/// number of cubes written
+pub fn write_csv<D: Int, A: Packable + Action>(
+    book: &Book<D, A>,
+    writer: impl Write,
+    options: &TextOptions,
+) -> io::Result<u64> {
+    let mut writer = BufWriter::new(writer);
+    writeln!(writer, "state,depth{}", if options.words { ",word" } else { "" })?;
+    let mut count = 0;
+    for entry in book.entries() {
+        let (cube, entry) = entry?;
+        write!(writer, "{},{}", text_state(&cube, options.states), entry.depth.into())?;
+        if options.words {
+            write!(writer, ",{}", text_word(entry.solution).unwrap_or_default())?;
+        }
+        writeln!(writer)?;
+        count += 1;
+    }
+    writer.flush()?;
+    Ok(count)
+}
+
+/// Write `book` as one JSON object per line, e.g. `{"state":"...","depth":3,"word":"R U F'"}`,
+/// returning the number of cubes written
+pub fn write_jsonl<D: Int, A: Packable + Action>(
+    book: &Book<D, A>,
+    writer: impl Write,
+    options: &TextOptions,
+) -> io::Result<u64> {
+    let mut writer = BufWriter::new(writer);
+    let mut count = 0;
+    for entry in book.entries() {
+        let (cube, entry) = entry?;
+        write!(writer, "{{\"state\":\"{}\",\"depth\":{}", text_state(&cube, options.states), entry.depth.into())?;
+        if options.words {
+            match text_word(entry.solution) {
+                Some(word) => write!(writer, ",\"word\":\"{word}\"")?,
+                None => write!(writer, ",\"word\":null")?,
+            }
+        }
+        writeln!(writer, "}}")?;
+        count += 1;
+    }
+    writer.flush()?;
+    Ok(count)
+}
+
+/// Insert every cube of a CSV file written by [`write_csv`] into `book`, returning the number of
+/// lines read. Either state encoding is accepted and depths are merged with the book's policy.
+pub fn read_csv<D: Int, A: Packable + Action>(book: &Book<D, A>, reader: impl BufRead) -> io::Result<u64> {
+    let mut count = 0;
+    for (i, line) in reader.lines().enumerate() {
+        let line = line?;
+        let line = line.trim();
+        if line.is_empty() || (i == 0 && line.starts_with("state")) {
+            continue;
+        }
+        let mut fields = line.split(',').map(|field| field.trim().trim_matches('"'));
+        let (Some(state), Some(depth)) = (fields.next(), fields.next()) else {
+            return Err(invalid_line(i, "expected a state and a depth"));
+        };
+        let depth = depth.parse().map_err(|_| invalid_line(i, "invalid depth"))?;
+        insert_text(book, state, depth, fields.next().filter(|word| !word.is_empty()))
+            .map_err(|e| invalid_line(i, &e.to_string()))?;
+        count += 1;
+    }
+    Ok(count)
+}
+
+/// Insert every cube of a JSON Lines file written by [`write_jsonl`] into `book`, returning the
+/// number of lines read. Either state encoding is accepted and depths are merged with the book's
+/// policy.
+pub fn read_jsonl<D: Int, A: Packable + Action>(book: &Book<D, A>, reader: impl BufRead) -> io::Result<u64> {
+    let mut count = 0;
+    for (i, line) in reader.lines().enumerate() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let fields = parse_json_object(&line).ok_or_else(|| invalid_line(i, "expected a flat JSON object"))?;
+        let field = |name: &str| fields.iter().find(|(key, _)| key == name).and_then(|(_, value)| value.as_deref());
+        let state = field("state").ok_or_else(|| invalid_line(i, "missing state"))?;
+        let depth = field("depth")
+            .and_then(|depth| depth.parse().ok())
+            .ok_or_else(|| invalid_line(i, "missing or invalid depth"))?;
+        insert_text(book, state, depth, field("word")).map_err(|e| invalid_line(i, &e.to_string()))?;
+        count += 1;
+    }
+    Ok(count)
+}
+
+fn text_state(cube: &Cube<Position>, states: TextState) -> String {
+    match states {
+        TextState::Facelets => cube.facelets(),
+        TextState::Rotations => cube.to_string(),
+    }
+}
+
+fn text_word<A: Action>(solution: Option<Vec<A>>) -> Option<String> {
+    let mut word = Word::new_lazy();
+    word.extend(solution?);
+    Some(word.display(Notation::Plain).to_string())
+}
+
+fn insert_text<D: Int, A: Packable + Action>(
+    book: &Book<D, A>,
+    state: &str,
+    depth: u64,
+    word: Option<&str>,
+) -> io::Result<()> {
+    let cube = match state.len() {
+        54 => Cube::from_facelets(state)
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, format!("Invalid facelets: {state}")))?,
+        _ => state.parse()?,
+    };
+    let depth = depth_from_u64(depth)
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, format!("Depth {depth} is too large for {}", D::TAG)))?;
+    let solution = word.map(|word| word.parse::<Word<A>>().map(|word| word.actions())).transpose()?;
+    book.insert_entry(&cube, Entry { depth, best: ActionSet::new(), solution })?;
+    Ok(())
+}
+
+fn invalid_line(index: usize, message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, format!("Line {}: {message}", index + 1))
+}
+
+/// `depth` as the book's depth type, if it fits
+fn depth_from_u64<D: Int>(depth: u64) -> Option<D> {
+    let bytes = depth.to_le_bytes();
+    bytes[D::BYTES..].iter().all(|&b| b == 0).then(|| D::from_bytes(&bytes[..D::BYTES]))
+}
+
+/// The keys and values of a JSON object whose values are all strings, numbers or `null`. Numbers are
+/// kept as their text and `null` is `None`.
+fn parse_json_object(line: &str) -> Option<Vec<(String, Option<String>)>> {
+    type Chars<'a> = std::iter::Peekable<std::str::Chars<'a>>;
+    fn skip_whitespace(chars: &mut Chars) {
+        while chars.next_if(|c| c.is_whitespace()).is_some() {}
+    }
+    fn string(chars: &mut Chars) -> Option<String> {
+        if chars.next()? != '"' {
+            return None;
+        }
+        let mut s = String::new();
+        loop {
+            match chars.next()? {
+                '"' => return Some(s),
+                '\\' => match chars.next()? {
+                    'n' => s.push('\n'),
+                    't' => s.push('\t'),
+                    'u' => {
+                        let hex: String = (0..4).map(|_| chars.next()).collect::<Option<_>>()?;
+                        s.push(char::from_u32(u32::from_str_radix(&hex, 16).ok()?)?);
+                    }
+                    c => s.push(c),
+                },
+                c => s.push(c),
+            }
+        }
+    }
+
+    let mut chars = line.trim().chars().peekable();
+    if chars.next()? != '{' {
+        return None;
+    }
+    let mut fields = vec![];
+    skip_whitespace(&mut chars);
+    if chars.next_if_eq(&'}').is_some() {
+        return Some(fields);
+    }
+    loop {
+        skip_whitespace(&mut chars);
+        let key = string(&mut chars)?;
+        skip_whitespace(&mut chars);
+        chars.next_if_eq(&':')?;
+        skip_whitespace(&mut chars);
+        let value = match chars.peek()? {
+            '"' => Some(string(&mut chars)?),
+            _ => {
+                let mut raw = String::new();
+                while let Some(c) = chars.next_if(|&c| c != ',' && c != '}' && !c.is_whitespace()) {
+                    raw.push(c);
+                }
+                match raw.as_str() {
+                    "null" => None,
+                    "" => return None,
+                    _ => Some(raw),
+                }
+            }
+        };
+        fields.push((key, value));
+        skip_whitespace(&mut chars);
+        match chars.next()? {
+            ',' => continue,
+            '}' => break,
+            _ => return None,
+        }
+    }
+    chars.next().is_none().then_some(fields)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{action::Turn, word::Word};
+
+    // The shape and data of a .npy file written by `npy`
+    fn read_npy(bytes: &[u8]) -> (Vec<usize>, Vec<u8>) {
+        assert_eq!(&bytes[..8], b"\x93NUMPY\x01\x00");
+        let len = u16::from_le_bytes([bytes[8], bytes[9]]) as usize;
+        assert_eq!((10 + len) % 64, 0);
+        let header = std::str::from_utf8(&bytes[10..10 + len]).unwrap();
+        let shape = header.split("'shape': (").nth(1).unwrap().split(')').next().unwrap();
+        let shape = shape.split(',').filter_map(|d| d.trim().parse().ok()).collect();
+        (shape, bytes[10 + len..].to_vec())
+    }
+
+    fn read_npz(path: &Path, array: &str) -> (Vec<usize>, Vec<u8>) {
+        let mut zip = zip::ZipArchive::new(File::open(path).unwrap()).unwrap();
+        let mut bytes = vec![];
+        zip.by_name(&format!("{array}.npy")).unwrap().read_to_end(&mut bytes).unwrap();
+        read_npy(&bytes)
+    }
+
+    #[test]
+    fn test_write_npy() {
+        let dir = std::env::temp_dir().join(format!("rubiks_test_write_npy_{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        let book: Book<u16, Turn> = Book::in_memory();
+        book.insert(Word::new(), 0).unwrap();
+        for &t in Turn::ALL {
+            let mut word = Word::new();
+            word.extend([t]);
+            book.insert(word, 1).unwrap();
+        }
+
+        // 19 cubes in shards of 8
+        let options = NpyOptions { shard_size: 8, best_actions: true, ..Default::default() };
+        let paths = write_npy(&book, &dir, &options).unwrap();
+        assert_eq!(paths.len(), 3);
+        let (shape, states) = read_npz(&paths[0], "states");
+        assert_eq!(shape, [8, 20]);
+        assert_eq!(&states[..20], &[0; 20]);
+        assert_eq!(read_npz(&paths[2], "depths").0, [3]);
+        assert_eq!(read_npz(&paths[2], "best").0, [3, 18]);
+
+        let options = NpyOptions { shard_size: 8, states: StateEncoding::Stickers, order: Order::Depth, format: NpyFormat::Npy, ..Default::default() };
+        let paths = write_npy(&book, &dir, &options).unwrap();
+        assert_eq!(paths.len(), 6);
+        let (shape, depths) = read_npy(&fs::read(&paths[1]).unwrap());
+        assert_eq!(shape, [8]);
+        assert_eq!(&depths[..4], &[0, 0, 1, 0]);
+        assert_eq!(read_npy(&fs::read(&paths[0]).unwrap()).0, [8, 54]);
+
+        // Shuffling keeps every cube, is repeatable and leaves no temporary files behind
+        let shuffled = |seed| {
+            let options = NpyOptions { shard_size: 8, order: Order::Shuffled(seed), ..Default::default() };
+            write_npy(&book, &dir, &options).unwrap().iter()
+                .flat_map(|path| read_npz(path, "states").1)
+                .collect::<Vec<_>>()
+        };
+        let first = shuffled(7);
+        assert_eq!(first, shuffled(7));
+        assert_ne!(first, shuffled(8));
+        let mut rows: Vec<_> = first.chunks(20).collect();
+        rows.sort();
+        rows.dedup();
+        assert_eq!(rows.len(), 19);
+        assert_eq!(fs::read_dir(&dir).unwrap().count(), 3);
+
+        // Exporting again into the same directory replaces all the old shards
+        let options = NpyOptions { shard_size: 4, ..Default::default() };
+        assert_eq!(write_npy(&book, &dir, &options).unwrap().len(), 5);
+        let options = NpyOptions { shard_size: 10, ..Default::default() };
+        let paths = write_npy(&book, &dir, &options).unwrap();
+        let mut files: Vec<_> = fs::read_dir(&dir).unwrap().map(|file| file.unwrap().path()).collect();
+        files.sort();
+        assert_eq!(files, paths);
+
+        let _ = fs::remove_dir_all(dir);
+    }
+
+    #[test]
+    fn test_text_round_trip() {
+        let book: Book<u8, Turn> = Book::in_memory();
+        book.insert(Word::new(), 0).unwrap();
+        for &t in Turn::ALL {
+            let mut word = Word::new();
+            word.extend([t]);
+            let cube = word.cube().clone();
+            let solution = Some(word.inverse().actions());
+            book.insert_entry(&cube, Entry { depth: 1, best: ActionSet::new(), solution }).unwrap();
+        }
+        let entries = |book: &Book<u8, Turn>| book.entries().map(Result::unwrap).collect::<Vec<_>>();
+
+        for states in [TextState::Facelets, TextState::Rotations] {
+            let options = TextOptions { states, words: true };
+            let mut csv = vec![];
+            assert_eq!(write_csv(&book, &mut csv, &options).unwrap(), 19);
+            let mut jsonl = vec![];
+            assert_eq!(write_jsonl(&book, &mut jsonl, &options).unwrap(), 19);
+            assert!(String::from_utf8(jsonl.clone()).unwrap().contains("\"word\":null"));
+
+            for i in 0..2 {
+                let copy: Book<u8, Turn> = Book::in_memory();
+                let read = if i == 0 { read_csv(&copy, &csv[..]) } else { read_jsonl(&copy, &jsonl[..]) };
+                assert_eq!(read.unwrap(), 19);
+                assert_eq!(entries(&copy), entries(&book));
+            }
+        }
+
+        let copy: Book<u8, Turn> = Book::in_memory();
+        assert!(read_csv(&copy, "state,depth\nAAAA,1\n".as_bytes()).is_err());
+        assert!(read_jsonl(&copy, "{\"state\":\"AAAAAAAAAAAAAAAAAAAA\",\"depth\":300}".as_bytes()).is_err());
+    }
+}
@@ -1,14 +1,20 @@
 pub mod action;
-pub use action::{Action, Move, Turn, QuarterTurn};
+pub use action::{Action, ActionSet, Canonical, Metric, Move, SliceTurn, Turn, QuarterTurn};
 pub mod book;
+pub mod compact;
 pub mod cube;
 pub use cube::{Cube, Position};
 pub mod cubelet;
 pub use cubelet::{Rotation, Axis};
+pub mod dataset;
+pub mod encode;
+pub mod export;
+pub mod sample;
+pub mod store;
 pub mod strategy;
 pub mod view;
 pub mod word;
-pub use word::Word;
+pub use word::{Equivalence, Solver, Word};
 // TODO: make compatible with laion/strategic_game_cube dataset to use as a benchmark
 // and to get results comparable with anyone else's for this very niche problem.
 // pub mod strategic_game_cube;
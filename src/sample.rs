@@ -0,0 +1,139 @@
+//! Drawing random cubes from a [`CompactBook`], either uniformly or with a quota for each depth so
+//! that the few shallow cubes aren't swamped by the many deep ones.
+//!
+//! A compact book lists its rows by depth, so sampling from it is uniform and never looks at a cube
+//! it doesn't return. A [`Book`](crate::book::Book) can only seek by key, and the cube after a random
+//! key is more likely to be one after a large gap in the keys, so sampling a book uniformly would
+//! mean reading all of it. Write the book out with [`Book::compact`](crate::book::Book::compact) and
+//! sample from that instead.
+
+#![allow(private_bounds)]
+use std::{collections::BTreeMap, io};
+
+use rand::{seq::{index, SliceRandom}, Rng};
+
+use crate::{
+    action::Action,
+    book::{Entry, Int, Packable},
+    compact::CompactBook,
+    cube::{Cube, Position},
+};
+
+/// A cube drawn from a book with everything recorded about it
+pub type Sample<D, A> = (Cube<Position>, Entry<D, A>);
+
+/// How many cubes to draw from each depth to get `n` cubes spread as evenly over the depths in
+/// `depth_counts` as they allow. A depth with too few cubes gives all of them and the rest of its
+/// share goes to the others.
+pub fn balanced_quotas(depth_counts: &BTreeMap<u64, u64>, n: usize) -> BTreeMap<u64, usize> {
+    let mut quotas: BTreeMap<u64, usize> = depth_counts.keys().map(|&depth| (depth, 0)).collect();
+    let mut left = n;
+    loop {
+        let open: Vec<u64> = quotas.iter()
+            .filter(|&(depth, &quota)| (quota as u64) < depth_counts[depth])
+            .map(|(&depth, _)| depth)
+            .collect();
+        if left == 0 || open.is_empty() {
+            return quotas;
+        }
+        // Deal the remainder out from the shallowest depth
+        let (share, extra) = (left / open.len(), left % open.len());
+        for (i, depth) in open.iter().enumerate() {
+            let room = (depth_counts[depth] - quotas[depth] as u64) as usize;
+            let give = (share + (i < extra) as usize).min(room);
+            *quotas.get_mut(depth).unwrap() += give;
+            left -= give;
+        }
+    }
+}
+
+impl<D: Int, A: Packable + Action> CompactBook<D, A> {
+    /// `n` different cubes picked uniformly at random, or every cube if the book has fewer
+    pub fn sample(&self, n: usize, rng: &mut impl Rng) -> Vec<Sample<D, A>> {
+        index::sample(rng, self.len(), n.min(self.len()))
+            .into_iter()
+            .map(|row| self.row(row).unwrap())
+            .collect()
+    }
+
+    /// Up to `quotas[depth]` different cubes picked uniformly from each depth, e.g. from
+    /// [`balanced_quotas`], in a random order. A depth gives every cube it has if it has too few.
+    /// Fails only if the book's list of rows by depth is corrupt.
+    pub fn sample_stratified(
+        &self,
+        quotas: &BTreeMap<u64, usize>,
+        rng: &mut impl Rng,
+    ) -> io::Result<Vec<Sample<D, A>>> {
+        let mut samples = vec![];
+        for (&depth, &quota) in quotas {
+            let count = self.rows_at_depth(depth).len();
+            for i in index::sample(rng, count, quota.min(count)) {
+                let row = self.row_at_depth(depth, i)?.unwrap();
+                samples.push(self.row(row).unwrap());
+            }
+        }
+        samples.shuffle(rng);
+        Ok(samples)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::{HashMap, HashSet};
+
+    use super::*;
+    use crate::{action::Turn, book::{fill, Book}};
+    use rand::{rngs::StdRng, SeedableRng};
+
+    #[test]
+    fn test_balanced_quotas() {
+        let counts = BTreeMap::from([(0, 1), (1, 18), (2, 243), (3, 3240)]);
+        assert_eq!(balanced_quotas(&counts, 40), BTreeMap::from([(0, 1), (1, 13), (2, 13), (3, 13)]));
+        assert_eq!(balanced_quotas(&counts, 100), BTreeMap::from([(0, 1), (1, 18), (2, 41), (3, 40)]));
+        assert_eq!(balanced_quotas(&counts, 10_000).values().sum::<usize>(), 3502);
+        assert!(balanced_quotas(&BTreeMap::new(), 10).is_empty());
+    }
+
+    #[test]
+    fn test_sample() {
+        let name = std::env::temp_dir().join(format!("rubiks_test_sample_{}.bin", std::process::id()));
+        let name = name.to_str().unwrap();
+        let book: Book<u16, Turn> = Book::in_memory();
+        fill(&book, 3);
+        book.compact(name).unwrap();
+        let compact: CompactBook<u16, Turn> = CompactBook::open(name).unwrap();
+        let counts = book.depth_counts().unwrap();
+        let quotas = balanced_quotas(&counts, 60);
+
+        let check = |samples: &[(Cube<Position>, Entry<u16, Turn>)], quotas: &BTreeMap<u64, usize>| {
+            let mut by_depth = BTreeMap::new();
+            for (cube, entry) in samples {
+                assert_eq!(book.get(cube).unwrap(), Some(entry.depth));
+                *by_depth.entry(entry.depth as u64).or_insert(0) += 1;
+            }
+            assert_eq!(&by_depth, quotas);
+            assert_eq!(samples.iter().map(|(cube, _)| cube).collect::<HashSet<_>>().len(), samples.len());
+        };
+
+        let mut rng = StdRng::seed_from_u64(3);
+        check(&compact.sample_stratified(&quotas, &mut rng).unwrap(), &quotas);
+
+        // The same seed draws the same cubes
+        let draw = |seed| compact.sample_stratified(&quotas, &mut StdRng::seed_from_u64(seed)).unwrap();
+        assert_eq!(draw(5), draw(5));
+        assert_ne!(draw(5), draw(6));
+
+        assert_eq!(compact.sample(20, &mut rng).len(), 20);
+        assert_eq!(compact.sample(10_000, &mut rng).len(), book.len());
+
+        // No cube turns up much more often than the others. Each is expected in about 1.4 of these.
+        let mut seen: HashMap<Cube<Position>, usize> = HashMap::new();
+        for _ in 0..100 {
+            for (cube, _) in compact.sample(50, &mut rng) {
+                *seen.entry(cube).or_default() += 1;
+            }
+        }
+        assert!(*seen.values().max().unwrap() <= 10);
+        let _ = std::fs::remove_file(name);
+    }
+}
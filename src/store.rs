@@ -0,0 +1,522 @@
+//! Where a [`Book`](crate::book::Book) keeps its bytes. A store holds two ordered key-value tables,
+//! one for the cubes and one for the book's metadata, and the book does everything else on top of
+//! them.
+
+use std::{
+    collections::BTreeMap,
+    fs::{self, File, OpenOptions},
+    io::{self, BufReader, BufWriter, Read, Write},
+    ops::Bound,
+    path::{Path, PathBuf},
+    sync::Mutex,
+};
+
+/// One of the two tables of a store
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Table {
+    /// The cubes of the book, keyed by their packed cubelets
+    Entries,
+    /// The book's format, merge policy, depth counts and so on
+    Meta,
+}
+
+impl Table {
+    fn index(self) -> usize {
+        match self {
+            Table::Entries => 0,
+            Table::Meta => 1,
+        }
+    }
+}
+
+/// Turns the current value of a key into the new one, see [`BookStore::update`]
+pub type Update<'a> = &'a mut dyn FnMut(Option<&[u8]>) -> Option<Vec<u8>>;
+
+/// Key-value pairs in key order
+pub type StoreIter<'a> = Box<dyn Iterator<Item = io::Result<(Vec<u8>, Vec<u8>)>> + 'a>;
+
+/// The storage a book is kept in. Keys are compared as bytes and every iterator runs in key order.
+pub trait BookStore: Send + Sync {
+    fn get(&self, table: Table, key: &[u8]) -> io::Result<Option<Vec<u8>>>;
+
+    fn insert(&self, table: Table, key: &[u8], value: &[u8]) -> io::Result<()>;
+
+    fn remove(&self, table: Table, key: &[u8]) -> io::Result<()>;
+
+    /// Replace the value of `key` with `f` of the current one, removing it if `f` returns `None`,
+    /// and return the value it had before. No other write to `key` can happen in between.
+    fn update(
+        &self,
+        table: Table,
+        key: &[u8],
+        f: Update<'_>,
+    ) -> io::Result<Option<Vec<u8>>>;
+
+    /// The pairs whose keys are between `start` and `end`
+    fn range(&self, table: Table, start: Bound<&[u8]>, end: Bound<&[u8]>) -> StoreIter<'_>;
+
+    /// The number of keys in `table`
+    fn len(&self, table: Table) -> usize;
+
+    /// Whether the store holds anything at all, i.e. whether a book has been made in it
+    fn is_empty(&self) -> bool {
+        self.len(Table::Entries) == 0 && self.len(Table::Meta) == 0
+    }
+
+    /// The number of bytes the store takes up on disk, which is 0 if it is only held in memory
+    fn size_on_disk(&self) -> io::Result<u64> {
+        Ok(0)
+    }
+
+    /// Make sure every write so far would survive the process stopping
+    fn flush(&self) -> io::Result<()> {
+        Ok(())
+    }
+
+    fn iter(&self, table: Table) -> StoreIter<'_> {
+        self.range(table, Bound::Unbounded, Bound::Unbounded)
+    }
+
+    /// The pairs whose keys start with `prefix`
+    fn scan_prefix<'a>(&'a self, table: Table, prefix: &'a [u8]) -> StoreIter<'a> {
+        Box::new(self.range(table, Bound::Included(prefix), Bound::Unbounded)
+            .take_while(move |entry| entry.as_ref().map_or(true, |(key, _)| key.starts_with(prefix))))
+    }
+}
+
+// Iterators copy this many pairs at a time, so the lock isn't held while the caller works
+const BATCH: usize = 1024;
+
+type Pairs = Vec<(Vec<u8>, Vec<u8>)>;
+
+// Iterate over a range of a map behind a lock, with `read_batch` copying the first BATCH pairs of
+// what is left of the range each time
+fn batched_range<'a>(
+    start: Bound<&[u8]>,
+    end: Bound<&[u8]>,
+    read_batch: impl Fn(Bound<Vec<u8>>, Bound<Vec<u8>>) -> Pairs + 'a,
+) -> StoreIter<'a> {
+    let end = end.map(<[u8]>::to_vec);
+    let mut next_start = start.map(<[u8]>::to_vec);
+    let mut batch = Vec::new().into_iter();
+    Box::new(std::iter::from_fn(move || {
+        if batch.len() == 0 {
+            let pairs = read_batch(next_start.clone(), end.clone());
+            if let Some((key, _)) = pairs.last() {
+                next_start = Bound::Excluded(key.clone());
+            }
+            batch = pairs.into_iter();
+        }
+        batch.next().map(Ok)
+    }))
+}
+
+fn read_batch(map: &BTreeMap<Vec<u8>, Vec<u8>>, start: Bound<Vec<u8>>, end: Bound<Vec<u8>>) -> Pairs {
+    map.range((start, end))
+        .take(BATCH)
+        .map(|(key, value)| (key.clone(), value.clone()))
+        .collect()
+}
+
+/// A store kept in a `BTreeMap`, for tests and books small enough to build in memory
+#[derive(Debug, Default)]
+pub struct MemoryStore {
+    tables: [Mutex<BTreeMap<Vec<u8>, Vec<u8>>>; 2],
+}
+
+impl MemoryStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl BookStore for MemoryStore {
+    fn get(&self, table: Table, key: &[u8]) -> io::Result<Option<Vec<u8>>> {
+        Ok(self.tables[table.index()].lock().unwrap().get(key).cloned())
+    }
+
+    fn insert(&self, table: Table, key: &[u8], value: &[u8]) -> io::Result<()> {
+        self.tables[table.index()].lock().unwrap().insert(key.to_vec(), value.to_vec());
+        Ok(())
+    }
+
+    fn remove(&self, table: Table, key: &[u8]) -> io::Result<()> {
+        self.tables[table.index()].lock().unwrap().remove(key);
+        Ok(())
+    }
+
+    fn update(
+        &self,
+        table: Table,
+        key: &[u8],
+        f: Update<'_>,
+    ) -> io::Result<Option<Vec<u8>>> {
+        let mut map = self.tables[table.index()].lock().unwrap();
+        let previous = map.get(key).cloned();
+        match f(previous.as_deref()) {
+            Some(value) => { map.insert(key.to_vec(), value); }
+            None => { map.remove(key); }
+        }
+        Ok(previous)
+    }
+
+    fn range(&self, table: Table, start: Bound<&[u8]>, end: Bound<&[u8]>) -> StoreIter<'_> {
+        let map = &self.tables[table.index()];
+        batched_range(start, end, move |start, end| read_batch(&map.lock().unwrap(), start, end))
+    }
+
+    fn len(&self, table: Table) -> usize {
+        self.tables[table.index()].lock().unwrap().len()
+    }
+}
+
+/// A store in a sled database, with one tree for each table
+#[derive(Clone)]
+pub struct SledStore {
+    // Db included to have access to the size_on_disk method
+    db: sled::Db,
+    tables: [sled::Tree; 2],
+}
+
+impl SledStore {
+    /// Open the database at `path`, creating it if it doesn't exist
+    pub fn open(path: impl AsRef<Path>) -> io::Result<Self> {
+        Self::from_db(sled::open(path)?)
+    }
+
+    pub fn from_db(db: sled::Db) -> io::Result<Self> {
+        let tables = [db.open_tree(b"book")?, db.open_tree(b"meta")?];
+        Ok(Self { db, tables })
+    }
+
+    /// Whether the database already existed when it was opened
+    pub fn was_recovered(&self) -> bool {
+        self.db.was_recovered()
+    }
+}
+
+impl BookStore for SledStore {
+    fn get(&self, table: Table, key: &[u8]) -> io::Result<Option<Vec<u8>>> {
+        Ok(self.tables[table.index()].get(key)?.map(|ivec| ivec.to_vec()))
+    }
+
+    fn insert(&self, table: Table, key: &[u8], value: &[u8]) -> io::Result<()> {
+        self.tables[table.index()].insert(key, value)?;
+        Ok(())
+    }
+
+    fn remove(&self, table: Table, key: &[u8]) -> io::Result<()> {
+        self.tables[table.index()].remove(key)?;
+        Ok(())
+    }
+
+    fn update(
+        &self,
+        table: Table,
+        key: &[u8],
+        f: Update<'_>,
+    ) -> io::Result<Option<Vec<u8>>> {
+        Ok(self.tables[table.index()].fetch_and_update(key, f)?.map(|ivec| ivec.to_vec()))
+    }
+
+    fn range(&self, table: Table, start: Bound<&[u8]>, end: Bound<&[u8]>) -> StoreIter<'_> {
+        Box::new(self.tables[table.index()].range::<&[u8], _>((start, end))
+            .map(|entry| entry.map(|(key, value)| (key.to_vec(), value.to_vec())).map_err(Into::into)))
+    }
+
+    fn len(&self, table: Table) -> usize {
+        self.tables[table.index()].len()
+    }
+
+    fn size_on_disk(&self) -> io::Result<u64> {
+        Ok(self.db.size_on_disk()?)
+    }
+
+    fn flush(&self) -> io::Result<()> {
+        self.db.flush()?;
+        Ok(())
+    }
+}
+
+// The file starts with this, followed by one record per write: the table, the key length and the
+// value length as little endian u32s (REMOVED for a removal), the key and the value
+const MAGIC: &[u8; 8] = b"RBKSTORE";
+const REMOVED: u32 = u32::MAX;
+const RECORD_HEADER_LEN: usize = 1 + 4 + 4;
+
+/// A store in a single file that every write is appended to, with every pair also held in memory.
+/// Writes are buffered until [`BookStore::flush`] or the store is dropped, which suits filling a
+/// book in bulk and then reading it many times. Opening replays the file, rewriting it without the
+/// overwritten pairs once they make up most of it.
+pub struct FileStore {
+    path: PathBuf,
+    state: Mutex<FileState>,
+}
+
+struct FileState {
+    tables: [BTreeMap<Vec<u8>, Vec<u8>>; 2],
+    log: BufWriter<File>,
+}
+
+impl FileStore {
+    /// Make a new, empty store at `path`, failing if the file already exists
+    pub fn create(path: impl AsRef<Path>) -> io::Result<Self> {
+        let path = path.as_ref().to_path_buf();
+        let mut file = OpenOptions::new().write(true).create_new(true).open(&path)?;
+        file.write_all(MAGIC)?;
+        let state = FileState { tables: Default::default(), log: BufWriter::new(file) };
+        Ok(Self { path, state: Mutex::new(state) })
+    }
+
+    /// Open the store at `path`. A record cut short by a crash at the end of the file is dropped, but
+    /// a whole record that makes no sense fails with [`io::ErrorKind::InvalidData`] and leaves the
+    /// file as it is.
+    pub fn open(path: impl AsRef<Path>) -> io::Result<Self> {
+        let path = path.as_ref().to_path_buf();
+        let mut reader = BufReader::new(File::open(&path)?);
+        let mut magic = [0; 8];
+        reader.read_exact(&mut magic)?;
+        if &magic != MAGIC {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, format!("{} is not a book store", path.display())));
+        }
+
+        let mut tables: [BTreeMap<Vec<u8>, Vec<u8>>; 2] = Default::default();
+        let (mut records, mut valid_len) = (0, MAGIC.len() as u64);
+        let corrupt = |at: u64| io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("{} has a corrupt record at byte {at}", path.display()),
+        );
+        while let Some((table, key, value)) = read_record(&mut reader)? {
+            if table > 1 {
+                return Err(corrupt(valid_len));
+            }
+            let table = &mut tables[table as usize];
+            valid_len += (RECORD_HEADER_LEN + key.len() + value.as_ref().map_or(0, Vec::len)) as u64;
+            records += 1;
+            match value {
+                Some(value) => { table.insert(key, value); }
+                None => { table.remove(&key); }
+            }
+        }
+
+        let live = tables.iter().map(BTreeMap::len).sum::<usize>();
+        let file = OpenOptions::new().append(true).open(&path)?;
+        file.set_len(valid_len)?;
+        let log = BufWriter::new(file);
+
+        let store = Self { path, state: Mutex::new(FileState { tables, log }) };
+        if records > 2 * live {
+            store.rewrite()?;
+        }
+        Ok(store)
+    }
+
+    /// Write the file again with only the current pairs
+    fn rewrite(&self) -> io::Result<()> {
+        let mut state = self.state.lock().unwrap();
+        state.log.flush()?;
+        let temp = self.path.with_extension("rewrite");
+        let mut writer = BufWriter::new(File::create(&temp)?);
+        writer.write_all(MAGIC)?;
+        for (i, table) in state.tables.iter().enumerate() {
+            for (key, value) in table {
+                write_record(&mut writer, i as u8, key, Some(value))?;
+            }
+        }
+        let file = writer.into_inner().map_err(|e| e.into_error())?;
+        file.sync_all()?;
+        fs::rename(&temp, &self.path)?;
+        state.log = BufWriter::new(OpenOptions::new().append(true).open(&self.path)?);
+        Ok(())
+    }
+}
+
+impl FileState {
+    fn write(&mut self, table: Table, key: &[u8], value: Option<Vec<u8>>) -> io::Result<()> {
+        write_record(&mut self.log, table.index() as u8, key, value.as_deref())?;
+        let map = &mut self.tables[table.index()];
+        match value {
+            Some(value) => { map.insert(key.to_vec(), value); }
+            None => { map.remove(key); }
+        }
+        Ok(())
+    }
+}
+
+fn write_record(writer: &mut impl Write, table: u8, key: &[u8], value: Option<&[u8]>) -> io::Result<()> {
+    let value_len = value.map_or(REMOVED, |value| value.len() as u32);
+    writer.write_all(&[table])?;
+    writer.write_all(&(key.len() as u32).to_le_bytes())?;
+    writer.write_all(&value_len.to_le_bytes())?;
+    writer.write_all(key)?;
+    writer.write_all(value.unwrap_or_default())
+}
+
+type Record = (u8, Vec<u8>, Option<Vec<u8>>);
+
+/// The next whole record, or `None` at the end of the file or of the last whole record. The table
+/// is not checked.
+fn read_record(reader: &mut impl Read) -> io::Result<Option<Record>> {
+    let mut header = [0; RECORD_HEADER_LEN];
+    if !read_all(reader, &mut header)? {
+        return Ok(None);
+    }
+    let key_len = u32::from_le_bytes(header[1..5].try_into().unwrap()) as usize;
+    let value_len = u32::from_le_bytes(header[5..].try_into().unwrap());
+    let mut key = vec![0; key_len];
+    if !read_all(reader, &mut key)? {
+        return Ok(None);
+    }
+    if value_len == REMOVED {
+        return Ok(Some((header[0], key, None)));
+    }
+    let mut value = vec![0; value_len as usize];
+    if !read_all(reader, &mut value)? {
+        return Ok(None);
+    }
+    Ok(Some((header[0], key, Some(value))))
+}
+
+// Fill `buf`, returning false if the reader ran out first
+fn read_all(reader: &mut impl Read, buf: &mut [u8]) -> io::Result<bool> {
+    match reader.read_exact(buf) {
+        Ok(()) => Ok(true),
+        Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => Ok(false),
+        Err(e) => Err(e),
+    }
+}
+
+impl BookStore for FileStore {
+    fn get(&self, table: Table, key: &[u8]) -> io::Result<Option<Vec<u8>>> {
+        Ok(self.state.lock().unwrap().tables[table.index()].get(key).cloned())
+    }
+
+    fn insert(&self, table: Table, key: &[u8], value: &[u8]) -> io::Result<()> {
+        self.state.lock().unwrap().write(table, key, Some(value.to_vec()))
+    }
+
+    fn remove(&self, table: Table, key: &[u8]) -> io::Result<()> {
+        let mut state = self.state.lock().unwrap();
+        if state.tables[table.index()].contains_key(key) {
+            state.write(table, key, None)?;
+        }
+        Ok(())
+    }
+
+    fn update(
+        &self,
+        table: Table,
+        key: &[u8],
+        f: Update<'_>,
+    ) -> io::Result<Option<Vec<u8>>> {
+        let mut state = self.state.lock().unwrap();
+        let previous = state.tables[table.index()].get(key).cloned();
+        let value = f(previous.as_deref());
+        if value.is_some() || previous.is_some() {
+            state.write(table, key, value)?;
+        }
+        Ok(previous)
+    }
+
+    fn range(&self, table: Table, start: Bound<&[u8]>, end: Bound<&[u8]>) -> StoreIter<'_> {
+        batched_range(start, end, move |start, end| {
+            read_batch(&self.state.lock().unwrap().tables[table.index()], start, end)
+        })
+    }
+
+    fn len(&self, table: Table) -> usize {
+        self.state.lock().unwrap().tables[table.index()].len()
+    }
+
+    fn size_on_disk(&self) -> io::Result<u64> {
+        Ok(fs::metadata(&self.path)?.len())
+    }
+
+    fn flush(&self) -> io::Result<()> {
+        let mut state = self.state.lock().unwrap();
+        state.log.flush()?;
+        state.log.get_ref().sync_data()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn exercise(store: &dyn BookStore) {
+        assert!(store.is_empty());
+        for i in (0..3000u32).rev() {
+            store.insert(Table::Entries, &i.to_be_bytes(), &[i as u8]).unwrap();
+        }
+        store.insert(Table::Meta, b"count/a", b"1").unwrap();
+        store.insert(Table::Meta, b"count/b", b"2").unwrap();
+        store.insert(Table::Meta, b"other", b"3").unwrap();
+        store.remove(Table::Entries, &5u32.to_be_bytes()).unwrap();
+
+        assert_eq!(store.len(Table::Entries), 2999);
+        assert_eq!(store.get(Table::Entries, &7u32.to_be_bytes()).unwrap(), Some(vec![7]));
+        assert_eq!(store.get(Table::Entries, &5u32.to_be_bytes()).unwrap(), None);
+
+        let keys: Vec<_> = store.iter(Table::Entries).map(|entry| entry.unwrap().0).collect();
+        assert_eq!(keys.len(), 2999);
+        assert!(keys.windows(2).all(|pair| pair[0] < pair[1]));
+        let (start, end) = (1000u32.to_be_bytes(), 2500u32.to_be_bytes());
+        assert_eq!(store.range(Table::Entries, Bound::Included(&start), Bound::Excluded(&end)).count(), 1500);
+        let counts: Vec<_> = store.scan_prefix(Table::Meta, b"count/").map(|entry| entry.unwrap().1).collect();
+        assert_eq!(counts, [b"1", b"2"]);
+
+        let previous = store.update(Table::Meta, b"other", &mut |value| {
+            Some([value.unwrap(), b"4"].concat())
+        }).unwrap();
+        assert_eq!(previous.as_deref(), Some(&b"3"[..]));
+        assert_eq!(store.get(Table::Meta, b"other").unwrap().as_deref(), Some(&b"34"[..]));
+        store.update(Table::Meta, b"other", &mut |_| None).unwrap();
+        assert_eq!(store.len(Table::Meta), 2);
+        store.flush().unwrap();
+    }
+
+    #[test]
+    fn test_memory_store() {
+        exercise(&MemoryStore::new());
+    }
+
+    #[test]
+    fn test_file_store() {
+        let name = std::env::temp_dir().join(format!("rubiks_test_file_store_{}.bin", std::process::id()));
+        let name = name.to_str().unwrap();
+        let _ = fs::remove_file(name);
+        exercise(&FileStore::create(name).unwrap());
+        assert!(FileStore::create(name).is_err());
+
+        // Everything is read back, and a record cut short at the end is dropped
+        let len = fs::metadata(name).unwrap().len();
+        OpenOptions::new().append(true).open(name).unwrap().write_all(&[0, 4, 0]).unwrap();
+        let store = FileStore::open(name).unwrap();
+        assert_eq!(store.len(Table::Entries), 2999);
+        assert_eq!(store.get(Table::Meta, b"count/b").unwrap(), Some(b"2".to_vec()));
+        assert_eq!(store.size_on_disk().unwrap(), len);
+        drop(store);
+
+        // A whole record for a table that doesn't exist is an error, not the end of the file
+        let mut bytes = fs::read(name).unwrap();
+        bytes[MAGIC.len()] = 7;
+        fs::write(name, &bytes).unwrap();
+        assert_eq!(FileStore::open(name).err().unwrap().kind(), io::ErrorKind::InvalidData);
+        assert_eq!(fs::read(name).unwrap(), bytes);
+        bytes[MAGIC.len()] = 0;
+        fs::write(name, &bytes).unwrap();
+        let store = FileStore::open(name).unwrap();
+        assert_eq!(store.len(Table::Entries), 2999);
+
+        // Overwriting most of it gets the stale records rewritten away on the next open
+        for i in 0..3000u32 {
+            store.insert(Table::Entries, &i.to_be_bytes(), &[0, 0]).unwrap();
+        }
+        drop(store);
+        let store = FileStore::open(name).unwrap();
+        assert_eq!(store.len(Table::Entries), 3000);
+        assert!(store.size_on_disk().unwrap() < 2 * len);
+        drop(store);
+        let _ = fs::remove_file(name);
+    }
+}
@@ -191,7 +191,7 @@ impl<T: Send + 'static> ThreadPool<T> {
 //         // let books = pool.spawn_all(spawner);
 // 
 //         // for other in books {
-//         //     book.extend_from_book(other);
+//         //     book.merge_from(&other);
 //         // }
 //     }
 // }
@@ -1,7 +1,12 @@
 // I want to put wrappers here that will change the way different things are formatted to strings
 use std::fmt::Display;
 
-use crate::cube::{Cube, index};
+use crate::{
+    action::{Action, Move},
+    cube::{Cube, index},
+    cubelet::Axis,
+    word::Word,
+};
 
 pub struct DisplayCube<T>(pub Cube<T>);
 
@@ -44,6 +49,123 @@ impl<T> Display for DisplayCube<T> {
     }
 }
 
+/// The ways a [`Word`] can be written out. Every style can be parsed back with `str::parse`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Notation {
+    /// Actions separated by spaces: `L R' U2`
+    Plain,
+    /// Like `Plain` but wrapped in parentheses: `(L R' U2)`
+    #[default]
+    Parentheses,
+    /// Actions padded to two characters so they line up in columns like on a WCA scramble
+    /// sheet: `L  R' U2`
+    Wca,
+    /// The internal form of each [`Move`]: `X10 X01 Z02`
+    Internal,
+    /// Consecutive turns of the same face written as a single power, whatever the actions are:
+    /// `L L R` becomes `L2 R`
+    Compact,
+}
+
+/// A [`Word`] written in a particular [`Notation`]
+pub struct DisplayWord<'a, T>(pub &'a Word<T>, pub Notation);
+
+impl<T: Action> Display for DisplayWord<'_, T> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let DisplayWord(word, notation) = self;
+        let tokens: Vec<String> = match notation {
+            Notation::Plain | Notation::Parentheses | Notation::Wca => word.moves().iter()
+                .flat_map(|&m| T::from_move(m))
+                .map(|a| a.to_string())
+                .collect(),
+            Notation::Internal => word.moves().iter().map(|m| m.to_string()).collect(),
+            Notation::Compact => {
+                let mut merged: Vec<Move> = vec![];
+                for &m in word.moves() {
+                    match merged.last_mut() {
+                        Some(last) if last.0 == m.0 => {
+                            *last = Move(last.0, (last.1 + m.1) % 4, (last.2 + m.2) % 4);
+                        }
+                        _ => merged.push(m),
+                    }
+                }
+                merged.into_iter().flat_map(compact_tokens).collect()
+            }
+        };
+
+        if *notation == Notation::Parentheses {
+            write!(f, "(")?;
+        }
+        for (i, token) in tokens.iter().enumerate() {
+            match (i, notation) {
+                (0, _) => {}
+                (_, Notation::Wca) => write!(f, "{}", " ".repeat(3 - tokens[i - 1].len().min(2)))?,
+                _ => write!(f, " ")?,
+            }
+            write!(f, "{}", token)?;
+        }
+        if *notation == Notation::Parentheses {
+            write!(f, ")")?;
+        }
+        Ok(())
+    }
+}
+
+const FACES: [(char, char); 3] = [('L', 'R'), ('F', 'B'), ('D', 'U')];
+
+fn power(turns: u8) -> &'static str {
+    match turns % 4 {
+        1 => "",
+        2 => "2",
+        3 => "'",
+        _ => unreachable!(),
+    }
+}
+
+fn compact_tokens(m: Move) -> Vec<String> {
+    let Move(axis, rot1, rot2) = m;
+    let (negative, positive) = FACES[axis as usize];
+    let mut tokens = vec![];
+    if rot1 != 0 {
+        tokens.push(format!("{}{}", negative, power(rot1)));
+    }
+    // Clockwise on the positive face is the opposite direction around the axis
+    if rot2 != 0 {
+        tokens.push(format!("{}{}", positive, power(4 - rot2)));
+    }
+    tokens
+}
+
+/// Parse a single action in face notation (`R`, `R2`, `R'`, `R3`, `R2'`) or the internal form of a
+/// [`Move`] (`X01`).
+pub(crate) fn parse_move(token: &str) -> Option<Move> {
+    if let Ok(m) = token.parse::<Move>() {
+        return Some(m);
+    }
+
+    let mut chars = token.chars();
+    let face = chars.next()?;
+    let (axis, positive) = FACES.iter()
+        .enumerate()
+        .find_map(|(axis, &(negative, positive))| {
+            if face == negative {
+                Some((axis, false))
+            } else if face == positive {
+                Some((axis, true))
+            } else {
+                None
+            }
+        })?;
+    let turns = match chars.as_str() {
+        "" => 1,
+        "2" | "2'" => 2,
+        "'" | "3" => 3,
+        _ => return None,
+    };
+
+    let axis = [Axis::X, Axis::Y, Axis::Z][axis];
+    Some(if positive { Move(axis, 0, 4 - turns) } else { Move(axis, turns, 0) })
+}
 
 // pub fn pad_outside(mut this: String, num: u8) -> String {
 //     let mut s = " ".repeat(num as usize);
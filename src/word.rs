@@ -1,28 +1,64 @@
-use std::{fmt::Display, marker::PhantomData};
-use crate::{Action, Move, Cube, Position};
+use std::{fmt::Display, io, marker::PhantomData, str::FromStr, sync::OnceLock};
+use crate::{
+    view::{self, DisplayWord, Notation},
+    Action, Axis, Metric, Move, Cube, Position, Rotation
+};
 
-// 
+/// A sequence of actions along with the state it leaves a solved cube in. The fields are kept
+/// private so that the two can never get out of sync.
+///
+/// By default the state is updated with every action. A word made with [`Word::new_lazy`] instead
+/// works out the state the first time [`Word::cube`] is called after a change, which is much
+/// cheaper when building lots of words and only looking at some of their states.
 #[derive(Clone, Debug)]
 pub struct Word<T> {
-    pub actions: Vec<Move>,
-    pub cube: Cube<Position>,
+    actions: Vec<Move>,
+    // Whenever this is set it is the state after `actions`
+    cube: OnceLock<Cube<Position>>,
+    lazy: bool,
     _phantom: PhantomData<T>,
 }
 
+/// What two words may differ by and still be equivalent. See [`Word::equivalent_up_to`].
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Equivalence {
+    /// They leave the cube in the same state
+    #[default]
+    Exact,
+    /// They are the same algorithm done with the cube held a different way
+    Rotation,
+    /// They are the same up to turning U before and after (adjusting the U face)
+    Auf,
+    /// Both of the above
+    RotationAndAuf,
+}
+
+/// Anything that can find a way to solve a cube, such as a [`crate::book::Book`]
+pub trait Solver<A: Action> {
+    /// A word that takes `cube` to the solved state, or `None` if none was found
+    fn solution(&self, cube: &Cube<Position>) -> io::Result<Option<Word<A>>>;
+}
+
 impl<T: Action> Display for Word<T> {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        let actions = self.actions.iter()
-            .flat_map(|&m| T::from_move(m))
-            .collect::<Vec<_>>();
-        write!(f, "(")?;
-        for (i, a) in actions.into_iter().enumerate() {
-            if i == 0 {
-                write!(f, "{}", a)?;
-            } else {
-                write!(f, " {}", a)?;
-            }
-        }
-        write!(f, ")")
+        write!(f, "{}", self.display(Notation::Parentheses))
+    }
+}
+
+/// Parses any of the [`Notation`]s, whatever the action type of the word. Parsing and displaying only
+/// round-trip up to how the action type writes its actions: `"R2"` parsed as a
+/// `Word<QuarterTurn>` is displayed as `(R R)`.
+impl<T: Action> FromStr for Word<T> {
+    type Err = io::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        let s = s.strip_prefix('(').and_then(|s| s.strip_suffix(')')).unwrap_or(s);
+        let actions = s.split_whitespace()
+            .map(|token| view::parse_move(token)
+                .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, token.to_owned())))
+            .collect::<io::Result<Vec<_>>>()?;
+        Ok(Self::from_moves(actions))
     }
 }
 
@@ -32,17 +68,231 @@ impl<T: Action> Default for Word<T> {
     }
 }
 
+impl<T> Word<T> {
+    /// The state the word leaves a solved cube in
+    pub fn cube(&self) -> &Cube<Position> {
+        self.cube.get_or_init(|| replay(Cube::default(), &self.actions))
+    }
+
+    /// The moves in the word as they are stored
+    pub fn moves(&self) -> &[Move] {
+        &self.actions
+    }
+
+    /// Whether the state is only worked out when it is asked for
+    pub fn is_lazy(&self) -> bool {
+        self.lazy
+    }
+
+    /// Whether the two words leave the cube in the same state, whatever actions they are written in
+    pub fn equivalent<B>(&self, other: &Word<B>) -> bool {
+        self.cube() == other.cube()
+    }
+
+    /// Whether the two words leave the cube in the same state once they are allowed to differ by
+    /// `equivalence`
+    pub fn equivalent_up_to<B>(&self, other: &Word<B>, equivalence: Equivalence) -> bool {
+        let rotations: &[Rotation] = match equivalence {
+            Equivalence::Exact | Equivalence::Auf => &[Rotation::Neutral],
+            Equivalence::Rotation | Equivalence::RotationAndAuf => &Rotation::VARIANTS,
+        };
+        let aufs = match equivalence {
+            Equivalence::Exact | Equivalence::Rotation => 0..1,
+            Equivalence::Auf | Equivalence::RotationAndAuf => 0..4,
+        };
+
+        let target = other.cube();
+        aufs.clone().any(|before| {
+            let cube = replay(Cube::default().make_move(Move(Axis::Z, 0, before)), &self.actions);
+            aufs.clone().any(|after| {
+                let cube = cube.clone().make_move(Move(Axis::Z, 0, after));
+                rotations.iter().any(|&rot| cube.reoriented(rot) == *target)
+            })
+        })
+    }
+
+    pub fn into_parts(self) -> (Cube<Position>, Vec<Move>) {
+        let cube = self.cube.into_inner().unwrap_or_else(|| replay(Cube::default(), &self.actions));
+        (cube, self.actions)
+    }
+
+    // Keep the state in step with a move that was just added to the end of `actions`
+    fn advance(&mut self, m: Move) {
+        if self.lazy {
+            self.cube.take();
+        } else if let Some(cube) = self.cube.get_mut() {
+            *cube = std::mem::take(cube).make_move(m);
+        }
+    }
+
+    fn with_moves(actions: Vec<Move>, lazy: bool) -> Self {
+        let cube = if lazy {
+            OnceLock::new()
+        } else {
+            OnceLock::from(replay(Cube::default(), &actions))
+        };
+        Self { actions, cube, lazy, _phantom: PhantomData }
+    }
+
+    // Back to the empty word, keeping the way the state is kept
+    fn reset(&mut self) {
+        self.actions.clear();
+        self.cube = OnceLock::from(Cube::default());
+    }
+}
+
+fn replay(cube: Cube<Position>, moves: &[Move]) -> Cube<Position> {
+    moves.iter().fold(cube, |cube, &m| cube.make_move(m))
+}
+
 impl<T: Action> Word<T> {
     pub fn new() -> Self {
-        Self {
-            actions: vec![],
+        Self::with_moves(vec![], false)
+    }
+
+    /// An empty word that only works out its state when [`Word::cube`] is called
+    pub fn new_lazy() -> Self {
+        Self::with_moves(vec![], true)
+    }
+
+    /// The same word, keeping its state the way [`Word::new_lazy`] does
+    pub fn into_lazy(mut self) -> Self {
+        self.lazy = true;
+        self
+    }
+
+    pub fn from_parts_unchecked(cube: Cube<Position>, actions: Vec<Move>) -> Self {
+        Self { cube: OnceLock::from(cube), actions, ..Default::default() }
+    }
+
+    /// The actions in the word
+    pub fn actions(&self) -> Vec<T> {
+        self.actions.iter()
+            .filter(|m| m.1 != 0 || m.2 != 0)
+            .flat_map(|&m| T::from_move(m))
+            .collect()
+    }
+
+
+    /// Write out the word in a particular notation
+    pub fn display(&self, notation: Notation) -> DisplayWord<'_, T> {
+        DisplayWord(self, notation)
+    }
+
+    /// Every state a solved cube passes through as the word is applied, along with the action that
+    /// produced it. The solved state itself is not included.
+    pub fn states(&self) -> Vec<(T, Cube<Position>)> {
+        self.iter_states().collect()
+    }
+
+    /// Borrowing version of [`Word::states`]
+    pub fn iter_states(&self) -> States<'_, T> {
+        States {
+            moves: self.actions.iter(),
+            pending: vec![].into_iter(),
             cube: Cube::default(),
-            _phantom: PhantomData
         }
     }
 
-    pub fn from_parts_unchecked(cube: Cube<Position>, actions: Vec<Move>) -> Self {
-        Self { cube, actions, ..Default::default() }
+    /// The number of actions in the word, as they are stored and returned by [`Word::actions`]
+    pub fn len(&self) -> usize {
+        self.actions.iter().map(|&m| T::length(m)).sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.actions.iter().all(|m| m.1 == 0 && m.2 == 0)
+    }
+
+    /// The length of the word in another metric, e.g. `len_in::<QuarterTurn>()` for the quarter
+    /// turn metric. This counts the [`Word::normal_form`], the way [`Word::make_move`] builds words,
+    /// so runs on one axis are merged first and `"R R"` counts the same as `"R2"` however the word
+    /// was made.
+    pub fn len_in<M: Metric>(&self) -> usize {
+        reduce(self.actions.iter().copied()).into_iter().map(M::length).sum()
+    }
+
+    /// The same word written with a different set of actions. Nothing is lost because every word
+    /// is stored as [`Move`]s.
+    pub fn convert<B: Action>(self) -> Word<B> {
+        Word {
+            actions: self.actions,
+            cube: self.cube,
+            lazy: self.lazy,
+            _phantom: PhantomData,
+        }
+    }
+
+    fn from_moves(actions: Vec<Move>) -> Self {
+        Self::with_moves(actions, false)
+    }
+
+    /// The word that undoes this one
+    pub fn inverse(&self) -> Self {
+        Self::with_moves(self.actions.iter().rev().map(Action::inverse).collect(), self.lazy)
+    }
+
+    /// This word followed by `other`
+    pub fn concat(&self, other: &Self) -> Self {
+        let mut word = self.clone();
+        word.actions.extend_from_slice(&other.actions);
+        if word.lazy {
+            word.cube.take();
+        } else if let Some(cube) = word.cube.get_mut() {
+            *cube = replay(std::mem::take(cube), &other.actions);
+        }
+        word
+    }
+
+    /// This word repeated `n` times
+    pub fn pow(&self, n: u32) -> Self {
+        let actions = self.actions.repeat(n as usize);
+        Self::with_moves(actions, self.lazy)
+    }
+
+    /// `a`, followed by this word, followed by the inverse of `a`
+    pub fn conjugate(&self, a: &Self) -> Self {
+        a.concat(self).concat(&a.inverse())
+    }
+
+    /// `a b a' b'`
+    pub fn commutator(a: &Self, b: &Self) -> Self {
+        a.concat(b).concat(&a.inverse()).concat(&b.inverse())
+    }
+
+    /// The word seen in a mirror across the plane between the two faces of `axis`, e.g. the
+    /// left-handed version of an algorithm is `mirrored(Axis::X)`. Its state is the mirror image
+    /// of this word's state.
+    pub fn mirrored(&self, axis: Axis) -> Self {
+        Self::with_moves(self.actions.iter().map(|m| m.mirrored(axis)).collect(), self.lazy)
+    }
+
+    /// The same algorithm done after turning the whole cube by `rot`. Its state is this word's
+    /// state seen from the new orientation (see [`Cube::reoriented`]). In standard notation `y` is
+    /// [`Rotation::Z3`], so `R U` becomes `F U`.
+    pub fn reoriented(&self, rot: Rotation) -> Self {
+        Self::with_moves(self.actions.iter().map(|m| m.reoriented(rot)).collect(), self.lazy)
+    }
+
+    /// The shortest word `solver` knows that leaves the cube in the same state as this one. This is
+    /// the word itself if the solver does not know the state or only knows a longer way.
+    pub fn shortest_equivalent(&self, solver: &impl Solver<T>) -> io::Result<Self> {
+        Ok(match solver.solution(self.cube())? {
+            Some(solution) if solution.len() < self.len() => solution.inverse(),
+            _ => self.clone(),
+        })
+    }
+
+    /// The number of times the word has to be repeated to return a solved cube to the solved state.
+    /// This can be as large as 1260 so it does not fit in a u8.
+    pub fn order(&self) -> u64 {
+        let solved = Cube::default();
+        let mut cube = self.cube().clone();
+        let mut order = 1;
+        while cube != solved {
+            cube = self.actions.iter().fold(cube, |cube, &m| cube.make_move(m));
+            order += 1;
+        }
+        order
     }
 
     pub fn make_move(&mut self, action: T) {
@@ -59,46 +309,140 @@ impl<T: Action> Word<T> {
                 self.actions.push(m);
             }
         } else {
-            self.actions.push(action.into());
+            self.actions.push(m);
+        }
+        self.advance(m);
+    }
+
+    /// Remove the last action, undoing it on the cube
+    pub fn pop(&mut self) -> Option<T> {
+        let last = loop {
+            let last = *self.actions.last()?;
+            if last.1 == 0 && last.2 == 0 {
+                self.actions.pop();
+            } else {
+                break last;
+            }
+        };
+
+        let action = *T::from_move(last).last().unwrap();
+        let m: Move = action.into();
+        let rest = Move(last.0, (last.1 + 4 - m.1) % 4, (last.2 + 4 - m.2) % 4);
+        if rest.1 == 0 && rest.2 == 0 {
+            self.actions.pop();
+        } else {
+            *self.actions.last_mut().unwrap() = rest;
+        }
+        self.advance(action.inverse().into());
+        Some(action)
+    }
+
+    /// Keep only the first `len` actions. Short tails are undone on the cube, long ones are
+    /// dropped and the rest of the word is replayed.
+    pub fn truncate(&mut self, len: usize) {
+        let current = self.len();
+        if len >= current {
+            return;
+        }
+
+        if current - len <= len {
+            for _ in len..current {
+                self.pop();
+            }
+        } else {
+            let mut actions = self.actions();
+            actions.truncate(len);
+            self.reset();
+            self.extend(actions);
+        }
+    }
+
+    /// Insert `action` so that it becomes the action at `index`
+    pub fn insert_at(&mut self, index: usize, action: T) {
+        self.splice(index..index, [action]);
+    }
+
+    /// Remove and return the action at `index`
+    pub fn remove_at(&mut self, index: usize) -> T {
+        self.splice(index..=index, []).pop().unwrap()
+    }
+
+    /// Replace the actions in `range` with `replace_with`, returning the actions that were
+    /// removed. Like [`Vec::splice`] this panics if the range is out of bounds.
+    pub fn splice<R, I>(&mut self, range: R, replace_with: I) -> Vec<T>
+    where
+        R: std::ops::RangeBounds<usize>,
+        I: IntoIterator<Item = T>,
+    {
+        let mut actions = self.actions();
+        let len = actions.len();
+        let start = match range.start_bound() {
+            std::ops::Bound::Included(&i) => i,
+            std::ops::Bound::Excluded(&i) => i + 1,
+            std::ops::Bound::Unbounded => 0,
+        };
+        let removed: Vec<T> = actions.splice(range, replace_with).collect();
+
+        if start + removed.len() == len && removed.len() <= start {
+            // Only the tail changed, so undo it rather than replaying everything
+            for _ in 0..removed.len() {
+                self.pop();
+            }
+            self.extend(actions.drain(start..));
+        } else {
+            self.reset();
+            self.extend(actions);
         }
-        self.cube = self.cube.clone().make_move(action.into());
+        removed
     }
 
     // We'll keep the word expanded until the user says otherwise
+    /// Fully reduce the word. Moves on the same axis are merged and moves that cancel are removed,
+    /// looking back as far as the cancellations reach, so `A B B' A'` reduces to the empty word.
+    /// The result never has two moves on the same axis next to each other.
     pub fn normal_form(self) -> Self {
-        let mut actions = vec![];
-        let cube = self.cube;
-        let mut old = self.actions.into_iter();
+        let actions = reduce(self.actions.iter().copied());
+        Self { actions, ..self }
+    }
+}
 
-        let mut first = old.next();
-        let mut second = old.next();
-        loop {
-            match (first, second) {
-                (None, _) => { break }
-                (Some(left), None) => { actions.push(left); break }
-                (Some(left), Some(right)) => {
-                    match Move::reduce(left, right) {
-                        (None, None) => {
-                            first = old.next();
-                            second = old.next();
-                        }
-                        (Some(new_left), None) => {
-                            first = Some(new_left);
-                            second = old.next();
-                        }
-                        (Some(same_left), Some(same_right)) => {
-                            actions.push(same_left);
-                            first = Some(same_right);
-                            second = old.next();
-                        }
-                        _ => unreachable!(),
-                    }
+// The moves of `Word::normal_form`
+fn reduce(moves: impl ExactSizeIterator<Item = Move>) -> Vec<Move> {
+    let mut actions: Vec<Move> = Vec::with_capacity(moves.len());
+    for m in moves {
+        match actions.last() {
+            Some(&last) if last.0 == m.0 => {
+                actions.pop();
+                if let (Some(merged), None) = Move::reduce(last, m) {
+                    actions.push(merged);
                 }
             }
+            _ if m.1 == 0 && m.2 == 0 => {}
+            _ => actions.push(m),
         }
+    }
+    actions
+}
 
+/// Iterator over the intermediate states of a [`Word`]. See [`Word::iter_states`].
+pub struct States<'a, T> {
+    moves: std::slice::Iter<'a, Move>,
+    pending: std::vec::IntoIter<T>,
+    cube: Cube<Position>,
+}
+
+impl<T: Action> Iterator for States<'_, T> {
+    type Item = (T, Cube<Position>);
 
-        Self::from_parts_unchecked(cube, actions)
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some(action) = self.pending.next() {
+                self.cube = self.cube.clone().make_move(action);
+                return Some((action, self.cube.clone()));
+            }
+            let &m = self.moves.find(|m| m.1 != 0 || m.2 != 0)?;
+            self.pending = T::from_move(m).into_iter();
+        }
     }
 }
 
@@ -106,8 +450,8 @@ impl<A: Action> Extend<A> for Word<A> {
     fn extend<T: IntoIterator<Item = A>>(&mut self, iter: T) {
         iter.into_iter().for_each(|action| {
             let m = action.into();
-            self.cube = self.cube.clone().make_move(m);
             self.actions.push(m);
+            self.advance(m);
         })
     }
 }
@@ -133,7 +477,7 @@ mod tests {
         word.make_move(Move(Axis::X, 1, 3));
         word.make_move(Move(Axis::X, 1, 3));
 
-        let Word { cube, actions, .. } = word.normal_form();
+        let (cube, actions) = word.normal_form().into_parts();
         let test_cube = Cube::default()
             .make_move(Move(Axis::Y, 0, 2))
             .make_move(Move(Axis::X, 2, 1));
@@ -145,4 +489,289 @@ mod tests {
         assert_eq!(cube, test_cube);
         assert_eq!(actions, test_actions);
     }
+
+    #[test]
+    fn test_normal_form_cancels_chains() {
+        use crate::Turn;
+
+        let mut word = Word::new();
+        word.extend([Turn::F, Turn::R, Turn::U, Turn::U3, Turn::R3, Turn::F3]);
+        let word = word.normal_form();
+        assert!(word.actions.is_empty());
+        assert_eq!(*word.cube(), Cube::default());
+
+        // L R L' is just R
+        let mut word = Word::new();
+        word.extend([Turn::L, Turn::U, Turn::U3, Turn::R, Turn::L3]);
+        assert_eq!(word.normal_form().actions, vec![Move::from(Turn::R)]);
+    }
+
+    #[test]
+    fn test_normal_form_properties() {
+        use rand::{thread_rng, Rng};
+        use crate::{QuarterTurn, Turn};
+
+        fn check<A: Action>() {
+            let mut rng = thread_rng();
+            for _ in 0..200 {
+                // Undoing recent actions now and then makes chains of cancellations likely
+                let mut actions: Vec<A> = vec![];
+                for _ in 0..rng.gen_range(0..30) {
+                    if !actions.is_empty() && rng.gen_bool(0.4) {
+                        let recent = rng.gen_range(actions.len().saturating_sub(3)..actions.len());
+                        actions.push(actions[recent].inverse());
+                    } else {
+                        actions.push(A::ALL[rng.gen_range(0..A::ALL.len())]);
+                    }
+                }
+                let mut word = Word::<A>::new();
+                word.extend(actions);
+
+                let length = |w: &Word<A>| w.actions.iter().map(|&m| A::from_move(m).len()).sum::<usize>();
+                let reduced = word.clone().normal_form();
+                assert_eq!(reduced.cube(), word.cube());
+                assert!(length(&reduced) <= length(&word), "{word} -> {reduced}");
+                assert!(reduced.actions.windows(2).all(|w| w[0].0 != w[1].0), "{reduced}");
+
+                let twice = reduced.clone().normal_form();
+                assert_eq!(twice.actions, reduced.actions);
+            }
+        }
+
+        check::<Move>();
+        check::<Turn>();
+        check::<QuarterTurn>();
+    }
+
+    #[test]
+    fn test_metrics() {
+        use crate::{QuarterTurn, SliceTurn, Turn};
+
+        let mut word = Word::new();
+        word.make_move(Turn::L);
+        word.make_move(Turn::R3);
+        assert_eq!((word.len_in::<Turn>(), word.len_in::<QuarterTurn>(), word.len_in::<SliceTurn>(), word.len_in::<Move>()), (2, 2, 1, 1));
+
+        let mut word = Word::new();
+        word.make_move(Turn::R2);
+        word.make_move(Turn::L2);
+        word.make_move(Turn::U);
+        assert_eq!((word.len_in::<Turn>(), word.len_in::<QuarterTurn>(), word.len_in::<SliceTurn>(), word.len_in::<Move>()), (3, 5, 2, 2));
+
+        let mut word = Word::new();
+        word.extend([Turn::L2, Turn::R]);
+        assert_eq!(word.len_in::<SliceTurn>(), 2);
+
+        // The same word counts the same whether it was parsed or built a move at a time
+        for text in ["L R", "L R'", "R R", "L U U' R", "R2 L2 U"] {
+            let parsed: Word<Turn> = text.parse().unwrap();
+            let mut built = Word::new();
+            for t in parsed.actions() {
+                built.make_move(t);
+            }
+            let lengths = |word: &Word<Turn>| {
+                (word.len_in::<Turn>(), word.len_in::<QuarterTurn>(), word.len_in::<SliceTurn>(), word.len_in::<Move>())
+            };
+            assert_eq!(lengths(&parsed), lengths(&built), "{text}");
+        }
+        let parsed: Word<Turn> = "L R".parse().unwrap();
+        assert_eq!(parsed.len_in::<Move>(), 1);
+        let parsed: Word<Turn> = "L R'".parse().unwrap();
+        assert_eq!(parsed.len_in::<SliceTurn>(), 1);
+
+        let quarter_turns = word.clone().convert::<QuarterTurn>();
+        assert_eq!(quarter_turns.to_string(), "(L L R)");
+        assert_eq!(quarter_turns.len(), 3);
+        assert_eq!(quarter_turns.cube(), word.cube());
+        assert_eq!(quarter_turns.convert::<Turn>().to_string(), word.to_string());
+    }
+
+    #[test]
+    fn test_notation_round_trip() {
+        use crate::{QuarterTurn, Turn};
+
+        let word: Word<Turn> = "(L R' U2 F B' D)".parse().unwrap();
+        assert_eq!(word.to_string(), "(L R' U2 F B' D)");
+        assert_eq!(word.display(Notation::Plain).to_string(), "L R' U2 F B' D");
+        assert_eq!(word.display(Notation::Wca).to_string(), "L  R' U2 F  B' D");
+        assert_eq!(word.display(Notation::Internal).to_string(), "X10 X01 Z02 Y10 Y01 Z10");
+
+        let quarter_turns = word.clone().convert::<QuarterTurn>();
+        assert_eq!(quarter_turns.display(Notation::Plain).to_string(), "L R' U U F B' D");
+        assert_eq!(quarter_turns.display(Notation::Compact).to_string(), "L R' U2 F B' D");
+
+        for notation in [Notation::Plain, Notation::Parentheses, Notation::Wca, Notation::Internal, Notation::Compact] {
+            fn check<A: Action>(word: &Word<A>, notation: Notation) {
+                let text = word.display(notation).to_string();
+                let parsed: Word<A> = text.parse().unwrap();
+                assert_eq!(parsed.display(notation).to_string(), text);
+                assert_eq!(parsed.cube(), word.cube());
+            }
+            check(&word, notation);
+            check(&quarter_turns, notation);
+            check(&word.clone().convert::<Move>(), notation);
+        }
+
+        assert!("R U x".parse::<Word<Turn>>().is_err());
+    }
+
+    #[test]
+    fn test_states() {
+        use crate::{QuarterTurn, Turn};
+
+        let word: Word<QuarterTurn> = "R U2 F'".parse().unwrap();
+        let states = word.states();
+        let actions: Vec<_> = states.iter().map(|(a, _)| *a).collect();
+        assert_eq!(actions, [QuarterTurn::R, QuarterTurn::U, QuarterTurn::U, QuarterTurn::F3]);
+        assert_eq!(&states.last().unwrap().1, word.cube());
+
+        let mut replayed = Cube::default();
+        for (action, cube) in word.iter_states() {
+            replayed = replayed.make_move(action);
+            assert_eq!(replayed, cube);
+        }
+
+        assert_eq!(Word::<Turn>::new().iter_states().count(), 0);
+    }
+
+    #[test]
+    fn test_editing() {
+        use crate::{QuarterTurn, Turn};
+
+        let replayed = |word: &Word<Turn>| word.actions().into_iter().fold(Cube::default(), |c, a| c.make_move(a));
+
+        let mut word: Word<Turn> = "L R' U2 F B".parse().unwrap();
+        word = word.normal_form();
+        assert_eq!(word.pop(), Some(Turn::B));
+        assert_eq!(word.to_string(), "(L R' U2 F)");
+        assert_eq!(*word.cube(), replayed(&word));
+
+        word.insert_at(1, Turn::D);
+        assert_eq!(word.to_string(), "(L D R' U2 F)");
+        assert_eq!(*word.cube(), replayed(&word));
+
+        assert_eq!(word.remove_at(0), Turn::L);
+        assert_eq!(word.to_string(), "(D R' U2 F)");
+        assert_eq!(*word.cube(), replayed(&word));
+
+        let removed = word.splice(2.., [Turn::B3, Turn::L2]);
+        assert_eq!(removed, [Turn::U2, Turn::F]);
+        assert_eq!(word.to_string(), "(D R' B' L2)");
+        assert_eq!(*word.cube(), replayed(&word));
+
+        word.truncate(3);
+        assert_eq!(word.to_string(), "(D R' B')");
+        assert_eq!(*word.cube(), replayed(&word));
+        word.truncate(1);
+        assert_eq!(word.to_string(), "(D)");
+        assert_eq!(*word.cube(), replayed(&word));
+
+        // Popping a quarter turn out of a half turn
+        let mut word: Word<QuarterTurn> = "R2".parse().unwrap();
+        assert_eq!(word.pop(), Some(QuarterTurn::R));
+        assert_eq!(word.to_string(), "(R)");
+        assert_eq!(word.pop(), Some(QuarterTurn::R));
+        assert_eq!(word.pop(), None);
+        assert_eq!(*word.cube(), Cube::default());
+    }
+
+    #[test]
+    fn test_group_operations() {
+        use crate::Turn;
+
+        let mut r = Word::new();
+        r.make_move(Turn::R);
+        let mut u = Word::new();
+        u.make_move(Turn::U);
+        let ru = r.concat(&u);
+
+        assert_eq!(*ru.concat(&ru.inverse()).cube(), Cube::default());
+        assert_eq!(ru.pow(3).cube(), ru.concat(&ru).concat(&ru).cube());
+        assert_eq!(*ru.pow(0).cube(), Cube::default());
+        assert_eq!(r.conjugate(&u).to_string(), "(U R U')");
+
+        let sexy = Word::commutator(&r, &u);
+        assert_eq!(sexy.to_string(), "(R U R' U')");
+
+        assert_eq!(Word::<Turn>::new().order(), 1);
+        assert_eq!(r.order(), 4);
+        assert_eq!(sexy.order(), 6);
+        assert_eq!(ru.order(), 105);
+        assert_eq!(*ru.pow(105).cube(), Cube::default());
+    }
+
+    #[test]
+    fn test_lazy_state() {
+        use crate::Turn;
+
+        let eager: Word<Turn> = "R U R' F2 D' B L2".parse().unwrap();
+        let mut lazy = Word::new_lazy();
+        lazy.extend(eager.actions());
+        assert!(lazy.is_lazy());
+        assert!(lazy.cube.get().is_none());
+        assert_eq!(lazy.cube(), eager.cube());
+
+        lazy.make_move(Turn::U);
+        assert!(lazy.cube.get().is_none());
+        assert_eq!(lazy.pop(), Some(Turn::U));
+        lazy.truncate(5);
+        let mut eager = eager;
+        eager.truncate(5);
+        assert_eq!(lazy.cube(), eager.cube());
+        assert_eq!(lazy.inverse().cube(), eager.inverse().cube());
+        assert!(lazy.inverse().is_lazy());
+        assert_eq!(lazy.clone().normal_form().into_parts(), eager.clone().normal_form().into_parts());
+
+        // Words can still be shared between threads
+        fn shared<T: Send + Sync>(_: &T) {}
+        shared(&lazy);
+        std::thread::scope(|s| { s.spawn(|| lazy.cube().clone()); });
+    }
+
+    #[test]
+    fn test_equivalence() {
+        use crate::Turn;
+
+        let sexy: Word<Turn> = "R U R' U'".parse().unwrap();
+        assert!(sexy.equivalent(&sexy.pow(7)));
+        assert!(!sexy.equivalent(&sexy.pow(2)));
+        assert!(sexy.equivalent(&sexy.clone().convert::<Move>()));
+
+        let rotated: Word<Turn> = "B U B' U'".parse().unwrap();
+        assert!(!sexy.equivalent(&rotated));
+        assert!(sexy.equivalent_up_to(&rotated, Equivalence::Rotation));
+        assert!(!sexy.equivalent_up_to(&rotated, Equivalence::Auf));
+
+        let adjusted: Word<Turn> = "U R U R' U2".parse().unwrap();
+        assert!(!sexy.equivalent_up_to(&adjusted, Equivalence::Rotation));
+        assert!(sexy.equivalent_up_to(&adjusted, Equivalence::Auf));
+
+        let both: Word<Turn> = "B U B' U2".parse().unwrap();
+        assert!(!sexy.equivalent_up_to(&both, Equivalence::Auf));
+        assert!(sexy.equivalent_up_to(&both, Equivalence::RotationAndAuf));
+    }
+
+    #[test]
+    fn test_transforms() {
+        use crate::{QuarterTurn, Turn};
+
+        let word: Word<Turn> = "R U R'".parse().unwrap();
+        let mirrored = word.mirrored(Axis::X);
+        assert_eq!(mirrored.to_string(), "(L' U' L)");
+        assert_eq!(*mirrored.cube(), word.cube().mirrored(Axis::X));
+        assert_eq!(mirrored.mirrored(Axis::X).to_string(), "(R U R')");
+
+        let word: Word<Turn> = "R U".parse().unwrap();
+        assert_eq!(word.reoriented(Rotation::Z3).to_string(), "(F U)");
+
+        let word: Word<QuarterTurn> = "R U F' L D2 B' L' U' R2 D".parse().unwrap();
+        for axis in [Axis::X, Axis::Y, Axis::Z] {
+            assert_eq!(*word.mirrored(axis).cube(), word.cube().mirrored(axis));
+        }
+        for rot in Rotation::VARIANTS {
+            let reoriented = word.reoriented(rot);
+            assert_eq!(*reoriented.cube(), word.cube().reoriented(rot));
+            assert!(word.equivalent_up_to(&reoriented, Equivalence::Rotation));
+        }
+    }
 }